@@ -0,0 +1,125 @@
+use std::fmt;
+
+// Arbitrary-size unsigned integer, stored as little-endian base 2^32 limbs.
+// Only what the slope products need: multiplying by machine-sized counts
+// and printing in decimal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BigUint(Vec<u32>);
+
+#[derive(Debug, PartialEq)]
+pub struct Overflow;
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "result does not fit in 64 bits")
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(n: u64) -> Self {
+        let mut limbs = vec![n as u32, (n >> 32) as u32];
+        trim(&mut limbs);
+        BigUint(limbs)
+    }
+}
+
+fn trim(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+impl BigUint {
+    pub fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn mul_u64(&mut self, n: u64) {
+        let (lo, hi) = (n as u32 as u64, n >> 32);
+        let mut result = vec![0u32; self.0.len() + 2];
+        for (shift, factor) in [lo, hi].iter().enumerate() {
+            let mut carry = 0u64;
+            for (i, &limb) in self.0.iter().enumerate() {
+                let v = limb as u64 * factor + result[i + shift] as u64 + carry;
+                result[i + shift] = v as u32;
+                carry = v >> 32;
+            }
+            let mut i = self.0.len() + shift;
+            while carry > 0 {
+                let v = result[i] as u64 + carry;
+                result[i] = v as u32;
+                carry = v >> 32;
+                i += 1;
+            }
+        }
+        trim(&mut result);
+        self.0 = result;
+    }
+
+    pub fn to_u64(&self) -> Result<u64, Overflow> {
+        match self.0.len() {
+            0 => Ok(0),
+            1 => Ok(self.0[0] as u64),
+            2 => Ok(self.0[0] as u64 | (self.0[1] as u64) << 32),
+            _ => Err(Overflow),
+        }
+    }
+
+    // Divides in place by a small divisor, returning the remainder.
+    fn div_rem_u32(&mut self, divisor: u32) -> u32 {
+        let mut rem = 0u64;
+        for limb in self.0.iter_mut().rev() {
+            let v = rem << 32 | *limb as u64;
+            *limb = (v / divisor as u64) as u32;
+            rem = v % divisor as u64;
+        }
+        trim(&mut self.0);
+        rem as u32
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        // Peel off nine decimal digits at a time.
+        let mut n = self.clone();
+        let mut chunks = vec![];
+        while !n.is_zero() {
+            chunks.push(n.div_rem_u32(1_000_000_000));
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multiplies_past_u64() {
+        let mut n = BigUint::from(1);
+        for _ in 0..5 {
+            n.mul_u64(u32::MAX as u64 + 1);
+        }
+        assert_eq!(
+            n.to_string(),
+            "1461501637330902918203684832716283019655932542976"
+        );
+        assert_eq!(n.to_u64(), Err(Overflow));
+
+        let mut n = BigUint::from(6818112000 / 40);
+        n.mul_u64(40);
+        assert_eq!(n.to_u64(), Ok(6818112000));
+        n.mul_u64(0);
+        assert_eq!(n.to_string(), "0");
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::{Cell, Forest, Row};
+
+// Compact binary layout, little-endian:
+//
+//   magic    4 bytes  "FRST"
+//   version  1 byte
+//   width    u32
+//   height   u32
+//   cells    2 bits per cell, row-major, four cells per byte starting at
+//            the low bits; the last byte is zero-padded.
+pub const MAGIC: &[u8; 4] = b"FRST";
+const VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    EmptyRows,
+    // Width times height doesn't fit in a `usize`.
+    TooLarge,
+    Io(io::Error),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not a compact forest file"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+            DecodeError::EmptyRows => write!(f, "rows must have at least one cell"),
+            DecodeError::TooLarge => write!(f, "the forest is too large"),
+            DecodeError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(e: io::Error) -> Self {
+        DecodeError::Io(e)
+    }
+}
+
+impl Cell {
+    fn to_bits(self) -> u8 {
+        match self {
+            Cell::Open => 0,
+            Cell::Tree => 1,
+            Cell::BuiltOpen => 2,
+            Cell::BuiltTree => 3,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Cell::Open,
+            1 => Cell::Tree,
+            2 => Cell::BuiltOpen,
+            _ => Cell::BuiltTree,
+        }
+    }
+}
+
+impl Forest {
+    // The header has a single width, so every row must have that many cells.
+    pub fn write_compact<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let width = self.width();
+        if let Some(row) = self.rows.iter().position(|row| row.0.len() != width) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "row {} has {} cells where the first has {}",
+                    row + 1,
+                    self.rows[row].0.len(),
+                    width
+                ),
+            ));
+        }
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        w.write_all(&(width as u32).to_le_bytes())?;
+        w.write_all(&(self.height() as u32).to_le_bytes())?;
+
+        let mut packed = Vec::with_capacity(width * self.height() / 4 + 1);
+        let mut byte = 0u8;
+        let mut filled = 0;
+        for row in self.rows.iter() {
+            for col in 0..width {
+                byte |= row.cell(col).to_bits() << (2 * filled);
+                filled += 1;
+                if filled == 4 {
+                    packed.push(byte);
+                    byte = 0;
+                    filled = 0;
+                }
+            }
+        }
+        if filled > 0 {
+            packed.push(byte);
+        }
+
+        w.write_all(&packed)
+    }
+
+    pub fn read_compact<R: Read>(r: &mut R) -> Result<Forest, DecodeError> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let mut version = [0u8; 1];
+        r.read_exact(&mut version)?;
+        if version[0] != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version[0]));
+        }
+        let mut word = [0u8; 4];
+        r.read_exact(&mut word)?;
+        let width = u32::from_le_bytes(word) as usize;
+        r.read_exact(&mut word)?;
+        let height = u32::from_le_bytes(word) as usize;
+        if width == 0 && height > 0 {
+            return Err(DecodeError::EmptyRows);
+        }
+
+        width.checked_mul(height).ok_or(DecodeError::TooLarge)?;
+
+        // Rows are read one at a time, and through `take` so that memory only
+        // grows with the data actually there, whatever the header claims.
+        // `packed` holds the bytes from `packed[0] = byte number base` on; a
+        // byte can hold the end of one row and the start of the next.
+        let mut packed = vec![];
+        let mut base = 0;
+        let mut rows = vec![];
+        for row in 0..height {
+            let (first, end) = (row * width, (row + 1) * width);
+            let wanted = end.div_ceil(4) - base - packed.len();
+            let got = r.take(wanted as u64).read_to_end(&mut packed)?;
+            if got < wanted {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            rows.push(Row((first..end)
+                .map(|index| Cell::from_bits(packed[index / 4 - base] >> (2 * (index % 4))))
+                .collect()));
+            packed.drain(..end / 4 - base);
+            base = end / 4;
+        }
+
+        Ok(Forest { rows })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_compact_format() {
+        let mut forest: Forest = "..#.#\n#...#\n.#...".into();
+        forest.rows[1].build(0);
+        forest.rows[2].build(2);
+        let mut bytes = vec![];
+        forest.write_compact(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 4 + 1 + 4 + 4 + 4);
+
+        let decoded = Forest::read_compact(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded.to_string(), "..#.#\nX...#\n.#O..\n");
+
+        let ragged: Forest = "..#\n#.".into();
+        let mut bytes = vec![];
+        let err = ragged.write_compact(&mut bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut bytes = vec![];
+        Forest::from("#.").write_compact(&mut bytes).unwrap();
+        bytes[4] = 9;
+        match Forest::read_compact(&mut &bytes[..]) {
+            Err(DecodeError::UnsupportedVersion(9)) => {}
+            _ => panic!("expected a version error"),
+        }
+        match Forest::read_compact(&mut &b"#.#."[..]) {
+            Err(DecodeError::BadMagic) => {}
+            _ => panic!("expected a magic error"),
+        }
+        bytes[4] = VERSION;
+        bytes.pop();
+        match Forest::read_compact(&mut &bytes[..]) {
+            Err(DecodeError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            _ => panic!("expected a truncation error"),
+        }
+
+        // A header claiming a huge forest with no cells after it.
+        let mut huge = MAGIC.to_vec();
+        huge.push(VERSION);
+        huge.extend_from_slice(&[0xff; 8]);
+        match Forest::read_compact(&mut &huge[..]) {
+            Err(DecodeError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            _ => panic!("expected a truncation error"),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+
+use crate::{Cell, Forest, SLOPES};
+
+pub struct CellChange {
+    pub row: usize,
+    pub col: usize,
+    pub before: Cell,
+    pub after: Cell,
+}
+
+pub struct SlopeChange {
+    pub right: usize,
+    pub down: usize,
+    pub before: usize,
+    pub after: usize,
+}
+
+pub struct ForestDiff {
+    // (width, height) of both forests. Cells are only compared where the two
+    // overlap.
+    pub sizes: ((usize, usize), (usize, usize)),
+    pub cells: Vec<CellChange>,
+    pub slopes: Vec<SlopeChange>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MergeError {
+    DoesNotFit { rows: usize, cols: usize },
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::DoesNotFit { rows, cols } => write!(
+                f,
+                "patch overflows the base map by {} rows and {} columns",
+                rows, cols
+            ),
+        }
+    }
+}
+
+impl Forest {
+    pub fn diff(&self, other: &Forest) -> ForestDiff {
+        let mut cells = vec![];
+        for row in 0..self.height().min(other.height()) {
+            // Rows may differ in length, and `cell` wraps around.
+            let width = self.rows[row].0.len().min(other.rows[row].0.len());
+            for col in 0..width {
+                let before = self.rows[row].cell(col);
+                let after = other.rows[row].cell(col);
+                if before != after {
+                    cells.push(CellChange {
+                        row,
+                        col,
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+
+        let slopes = SLOPES
+            .iter()
+            .map(|&(right, down)| SlopeChange {
+                right,
+                down,
+                before: self.part2_aux(right, down),
+                after: other.part2_aux(right, down),
+            })
+            .collect();
+
+        ForestDiff {
+            sizes: (
+                (self.width(), self.height()),
+                (other.width(), other.height()),
+            ),
+            cells,
+            slopes,
+        }
+    }
+
+    // Overlays `patch` onto the region of this forest whose top-left cell is
+    // at (`row`, `col`). The patch has to fit entirely inside the map.
+    pub fn merge(&mut self, patch: &Forest, row: usize, col: usize) -> Result<(), MergeError> {
+        let rows = (row + patch.height()).saturating_sub(self.height());
+        let cols = (col + patch.width()).saturating_sub(self.width());
+        if rows > 0 || cols > 0 {
+            return Err(MergeError::DoesNotFit { rows, cols });
+        }
+
+        for (r, patch_row) in patch.rows.iter().enumerate() {
+            for c in 0..patch.width() {
+                self.rows[row + r].set(col + c, patch_row.cell(c));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ForestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ((w1, h1), (w2, h2)) = self.sizes;
+        if (w1, h1) != (w2, h2) {
+            writeln!(f, "size: {}x{} -> {}x{}", w1, h1, w2, h2)?;
+        }
+        writeln!(f, "{} cells changed", self.cells.len())?;
+        for change in self.cells.iter() {
+            writeln!(
+                f,
+                "  row {} col {}: {} -> {}",
+                change.row,
+                change.col,
+                change.before.to_char(),
+                change.after.to_char()
+            )?;
+        }
+        for slope in self.slopes.iter() {
+            writeln!(
+                f,
+                "right: {} down: {} = {} -> {} ({:+})",
+                slope.right,
+                slope.down,
+                slope.before,
+                slope.after,
+                slope.after as i64 - slope.before as i64
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diffs_cells_and_slopes() {
+        let base: Forest = "...\n...\n...".into();
+        let edited: Forest = "...\n.#.\n#..".into();
+        let diff = base.diff(&edited);
+        assert_eq!(diff.cells.len(), 2);
+        assert_eq!((diff.cells[0].row, diff.cells[0].col), (1, 1));
+        assert!(diff.cells[0].after == Cell::Tree);
+        let slope = &diff.slopes[0];
+        assert_eq!((slope.right, slope.down), (1, 1));
+        assert_eq!((slope.before, slope.after), (0, 1));
+
+        let ragged: Forest = "..#\n#.".into();
+        let full: Forest = "..#\n#.#".into();
+        assert!(ragged.diff(&full).cells.is_empty());
+    }
+
+    #[test]
+    fn merges_patch_into_region() {
+        let mut base: Forest = "....\n....\n....".into();
+        let patch: Forest = "##\n.#".into();
+        assert_eq!(
+            base.merge(&patch, 2, 3),
+            Err(MergeError::DoesNotFit { rows: 1, cols: 1 })
+        );
+        base.merge(&patch, 1, 2).unwrap();
+        assert_eq!(base.to_string(), "....\n..##\n...#\n");
+    }
+}
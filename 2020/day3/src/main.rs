@@ -1,3 +1,17 @@
+use std::env;
+use std::fmt;
+use std::fs;
+
+use bignum::BigUint;
+
+mod bignum;
+mod codec;
+mod diff;
+mod tui;
+
+// The (right, down) slopes checked in part 2.
+const SLOPES: [(usize, usize); 5] = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
+
 #[derive(Copy, Clone, PartialEq)]
 enum Cell {
     Open,
@@ -17,8 +31,8 @@ impl From<char> for Cell {
 }
 
 impl Cell {
-    pub fn to_char(&self) -> char {
-        match *self {
+    pub fn to_char(self) -> char {
+        match self {
             Cell::Open => '.',
             Cell::Tree => '#',
             Cell::BuiltOpen => 'O',
@@ -54,11 +68,22 @@ impl Row {
         }
     }
 
+    pub fn set(&mut self, pos: usize, cell: Cell) {
+        let index = pos % self.0.len();
+        self.0[index] = cell;
+    }
+
     pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for cell in self.0.iter() {
-            print!("{}", cell.to_char());
+            write!(f, "{}", cell.to_char())?;
         }
-        println!();
+        Ok(())
     }
 }
 
@@ -66,10 +91,13 @@ struct Forest {
     rows: Vec<Row>,
 }
 
+// Blank lines, such as a trailing one, are skipped: a row without cells has
+// nothing to wrap around.
 impl From<&str> for Forest {
     fn from(raw: &str) -> Self {
         let rows = raw
-            .split('\n')
+            .lines()
+            .filter(|line| !line.trim().is_empty())
             .map(|line| line.into())
             .collect::<Vec<Row>>();
         Forest { rows }
@@ -77,12 +105,36 @@ impl From<&str> for Forest {
 }
 
 impl Forest {
+    // 0 for a forest without rows.
+    pub fn width(&self) -> usize {
+        self.rows.first().map_or(0, |row| row.0.len())
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
     pub fn print(&self) {
         for row in self.rows.iter() {
             row.print();
         }
     }
 
+    // Loads either the text map or, if the file starts with its magic bytes,
+    // the compact binary format. A file without any rows is an error.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let forest = if bytes.starts_with(codec::MAGIC) {
+            Forest::read_compact(&mut &bytes[..]).map_err(|e| e.to_string())?
+        } else {
+            String::from_utf8_lossy(&bytes).as_ref().into()
+        };
+        if forest.rows.is_empty() {
+            return Err("the forest has no rows".to_string());
+        }
+        Ok(forest)
+    }
+
     pub fn part1(&self) -> u32 {
         let num_rows = self.rows.len();
         let mut row = 1usize;
@@ -100,8 +152,18 @@ impl Forest {
         num_trees
     }
 
-    fn part2_aux(&mut self, right: usize, down: usize) -> u32 {
+    // Rows wrap around, so on a rectangular map only `right` modulo the width
+    // matters and the column can't overflow. Steps are still checked, which
+    // only a ragged map with a huge `right` can trip.
+    fn part2_aux(&self, right: usize, down: usize) -> usize {
+        assert!(down > 0, "down must be at least 1");
         let num_rows = self.rows.len();
+        let width = self.width();
+        let right = if self.rows.iter().all(|row| row.0.len() == width) {
+            right % width.max(1)
+        } else {
+            right
+        };
         let mut row = down;
         let mut col = right;
         let mut num_trees = 0;
@@ -112,30 +174,85 @@ impl Forest {
             }
             // TODO(brunor): remove line below
             // self.rows[row].build(col);
-            row += down;
-            col += right;
+            row = match row.checked_add(down) {
+                Some(row) => row,
+                None => break,
+            };
+            col = col
+                .checked_add(right)
+                .expect("the slope runs too far right to follow");
         }
 
         num_trees
     }
 
-    fn part2(&mut self) -> usize {
-        let inputs = [
-            (1usize, 1usize),
-            (3usize, 1usize),
-            (5usize, 1usize),
-            (7usize, 1usize),
-            (1usize, 2usize),
-        ];
-        let mut res = 1usize;
-        for (right, down) in inputs.iter() {
-            let v = self.part2_aux(*right, *down) as usize;
+    // Every cell the ideal line from the centre of the top-left cell passes
+    // through on its way down with the slope `right / down`, in order and
+    // without the starting cell. Columns are left unwrapped, `Row::cell`
+    // wraps them. A line going exactly through a corner moves diagonally
+    // without visiting the two cells that only touch it.
+    pub fn line_cells(&self, right: usize, down: usize) -> Vec<(usize, usize)> {
+        assert!(down > 0, "the line has to go down");
+        let num_rows = self.rows.len();
+        let mut cells = vec![];
+        let mut row = 0usize;
+        let mut col = 0usize;
+        loop {
+            // The line crosses the right edge of the current cell at
+            // t = (col + 0.5) / right and the bottom edge at
+            // t = (row + 0.5) / down; compare both without dividing.
+            let to_right = (2 * col + 1) * down;
+            let to_bottom = (2 * row + 1) * right;
+            if right == 0 || to_right > to_bottom {
+                row += 1;
+            } else if to_right < to_bottom {
+                col += 1;
+            } else {
+                row += 1;
+                col += 1;
+            }
+            if row >= num_rows {
+                break;
+            }
+            cells.push((row, col));
+        }
+
+        cells
+    }
+
+    // Counts the trees the toboggan brushes past, not only the ones in the
+    // cells where it lands.
+    pub fn trees_on_line(&self, right: usize, down: usize) -> usize {
+        self.line_cells(right, down)
+            .into_iter()
+            .filter(|&(row, col)| self.rows[row].cell(col) == Cell::Tree)
+            .count()
+    }
+
+    // Multiplies the tree counts of every slope. The product can outgrow any
+    // fixed-width integer on generated maps, hence the big integer.
+    fn slope_product(&self, slopes: &[(usize, usize)]) -> BigUint {
+        let mut res = BigUint::from(1);
+        for (right, down) in slopes.iter() {
+            let v = self.part2_aux(*right, *down);
             println!("right: {} down: {} = {}", right, down, v);
-            res *= v;
+            res.mul_u64(v as u64);
         }
-        // self.print();
         res
     }
+
+    fn part2(&self) -> BigUint {
+        self.slope_product(&SLOPES)
+    }
+}
+
+impl fmt::Display for Forest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.rows.iter() {
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
 }
 
 fn main() {
@@ -463,6 +580,131 @@ fn main() {
 .##.#.....#.......##.......#...
 ..#..##...#..........#.#....#.#";
     let mut trees: Forest = tile.into();
-    println!("Part 1: {}", trees.part1());
-    println!("Part 2: {}", trees.part2());
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let usage = "Usage: day3 [play | replay <right> <down> | line <right> <down> | \
+                 diff <a> <b> | merge <base> <patch> <row> <col> | \
+                 pack <in> <out> | unpack <in> <out> | slopes <right>,<down>...]";
+    let arg = |i: usize| -> usize {
+        args.get(i)
+            .and_then(|a| a.parse::<usize>().ok())
+            .expect(usage)
+    };
+    let path = |i: usize| -> &str { args.get(i).expect(usage) };
+    let load = |i: usize| {
+        Forest::load(path(i)).unwrap_or_else(|e| panic!("Could not load {}, {}", path(i), e))
+    };
+    match args.first().map(|a| a.as_str()) {
+        Some("play") => {
+            let hit = tui::play(&mut trees).expect("Could not run interactive mode");
+            trees.print();
+            println!("Trees hit: {}", hit);
+        }
+        Some("replay") => {
+            let (right, down) = (arg(1), arg(2));
+            assert!(down > 0, "down must be at least 1");
+            let hit = tui::replay(&mut trees, right, down).expect("Could not run replay mode");
+            trees.print();
+            println!("Trees hit: {}", hit);
+        }
+        Some("line") => {
+            let (right, down) = (arg(1), arg(2));
+            assert!(down > 0, "down must be at least 1");
+            println!("Landed on trees: {}", trees.part2_aux(right, down));
+            println!("Brushed past trees: {}", trees.trees_on_line(right, down));
+        }
+        Some("diff") => {
+            let (a, b) = (load(1), load(2));
+            print!("{}", a.diff(&b));
+        }
+        Some("merge") => {
+            let mut base = load(1);
+            let patch = load(2);
+            match base.merge(&patch, arg(3), arg(4)) {
+                Ok(()) => base.print(),
+                Err(e) => eprintln!("Could not merge: {}", e),
+            }
+        }
+        Some("pack") => {
+            let mut out = fs::File::create(path(2)).expect("Could not create output file");
+            load(1)
+                .write_compact(&mut out)
+                .expect("Could not write compact forest");
+        }
+        Some("unpack") => {
+            let forest = load(1);
+            fs::write(path(2), forest.to_string()).expect("Could not write forest file");
+        }
+        Some("slopes") => {
+            let slopes = args[1..]
+                .iter()
+                .map(|slope| {
+                    let mut parts = slope.split(',').map(|n| n.parse::<usize>().expect(usage));
+                    let (right, down) = (parts.next().expect(usage), parts.next().expect(usage));
+                    assert!(down > 0, "down must be at least 1 in {}", slope);
+                    (right, down)
+                })
+                .collect::<Vec<(usize, usize)>>();
+            let product = trees.slope_product(&slopes);
+            match product.to_u64() {
+                Ok(_) => println!("Product: {}", product),
+                Err(e) => println!("Product: {} ({})", product, e),
+            }
+        }
+        _ => {
+            println!("Part 1: {}", trees.part1());
+            println!("Part 2: {}", trees.part2());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn line_cells_follow_fractional_slopes() {
+        let forest: Forest = ".....\n.....\n.....\n.....".into();
+        assert_eq!(forest.line_cells(1, 1), vec![(1, 1), (2, 2), (3, 3)]);
+        assert_eq!(forest.line_cells(0, 1), vec![(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(
+            forest.line_cells(1, 2),
+            vec![(1, 0), (1, 1), (2, 1), (3, 1), (3, 2)]
+        );
+        assert_eq!(forest.line_cells(1, 3), vec![(1, 0), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn line_contains_landing_cells() {
+        let forest: Forest = "..##.......\n#...#...#..\n.#....#..#.\n..#.#...#.#\n.#...##..#.\n..#.##.....\n.#.#.#....#\n.#........#\n#.##...#...\n#...##....#\n.#..#...#.#".into();
+        for &(right, down) in [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)].iter() {
+            let line = forest.line_cells(right, down);
+            let mut row = down;
+            while row < forest.height() {
+                assert!(line.contains(&(row, row / down * right)));
+                row += down;
+            }
+            assert!(forest.trees_on_line(right, down) >= forest.part2_aux(right, down));
+        }
+        assert_eq!(forest.part2_aux(3, 1), 7);
+        assert_eq!(
+            forest.part2_aux(usize::MAX, 1),
+            forest.part2_aux(usize::MAX % 11, 1)
+        );
+    }
+
+    #[test]
+    fn skips_blank_rows_and_rejects_empty_forests() {
+        let forest: Forest = "..#\n#..\n\n".into();
+        assert_eq!((forest.width(), forest.height()), (3, 2));
+        assert_eq!(forest.diff(&"..#\n\n#.#".into()).cells.len(), 1);
+        let mut bytes = vec![];
+        forest.write_compact(&mut bytes).unwrap();
+
+        let empty: Forest = "".into();
+        assert_eq!(empty.width(), 0);
+        let path = env::temp_dir().join(format!("day3-empty-{}", std::process::id()));
+        fs::write(&path, "\n\n").unwrap();
+        assert!(Forest::load(path.to_str().unwrap()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,233 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use crate::{Cell, Forest};
+
+const VIEW_HEIGHT: usize = 20;
+
+// Keeps the terminal in raw mode while alive and restores the previous
+// settings when dropped, so a panic doesn't leave the shell unusable.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        Ok(RawMode {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed, is stdin a terminal?"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Key {
+    Left,
+    Right,
+    Down,
+    Quit,
+    Other,
+}
+
+fn read_key(input: &mut impl Read) -> io::Result<Key> {
+    let mut buf = [0u8; 1];
+    input.read_exact(&mut buf)?;
+    let key = match buf[0] {
+        b'h' | b'a' => Key::Left,
+        b'l' | b'd' => Key::Right,
+        b'j' | b's' | b' ' | b'\r' => Key::Down,
+        b'q' | 3 => Key::Quit,
+        // Arrow keys arrive as `ESC [ <letter>`.
+        0x1b => {
+            let mut seq = [0u8; 2];
+            input.read_exact(&mut seq)?;
+            match seq {
+                [b'[', b'D'] => Key::Left,
+                [b'[', b'C'] => Key::Right,
+                [b'[', b'B'] => Key::Down,
+                _ => Key::Other,
+            }
+        }
+        _ => Key::Other,
+    };
+
+    Ok(key)
+}
+
+pub struct Toboggan {
+    row: usize,
+    col: usize,
+    pub trees: u32,
+}
+
+impl Toboggan {
+    pub fn new(forest: &mut Forest) -> Self {
+        forest.rows[0].build(0);
+        Toboggan {
+            row: 0,
+            col: 0,
+            trees: 0,
+        }
+    }
+
+    // Moves `right` columns (negative values go left, wrapping around the
+    // tile) and `down` rows, marking the cell it lands on as visited.
+    // Returns false once the move would leave the bottom of the forest.
+    pub fn slide(&mut self, forest: &mut Forest, right: isize, down: usize) -> bool {
+        let row = self.row + down;
+        if row >= forest.height() {
+            return false;
+        }
+
+        let width = forest.width() as isize;
+        self.col = (self.col as isize + right).rem_euclid(width) as usize;
+        self.row = row;
+        if forest.rows[row].cell(self.col) == Cell::Tree {
+            self.trees += 1;
+        }
+        forest.rows[row].build(self.col);
+
+        true
+    }
+}
+
+fn render(forest: &Forest, toboggan: &Toboggan, help: &str) -> String {
+    let top = toboggan.row.saturating_sub(VIEW_HEIGHT / 2);
+    let bottom = (top + VIEW_HEIGHT).min(forest.height());
+
+    // Clear the screen and move the cursor home before drawing.
+    let mut frame = String::from("\x1b[2J\x1b[H");
+    for (index, row) in forest.rows[top..bottom].iter().enumerate() {
+        for col in 0..forest.width() {
+            if top + index == toboggan.row && col == toboggan.col {
+                frame.push_str("\x1b[7m@\x1b[0m");
+                continue;
+            }
+            match row.cell(col) {
+                Cell::Open => frame.push('.'),
+                Cell::Tree => frame.push_str("\x1b[32m#\x1b[0m"),
+                Cell::BuiltOpen => frame.push_str("\x1b[33mO\x1b[0m"),
+                Cell::BuiltTree => frame.push_str("\x1b[31mX\x1b[0m"),
+            }
+        }
+        // Raw mode doesn't translate `\n`, so return the carriage explicitly.
+        frame.push_str("\r\n");
+    }
+    frame.push_str(&format!(
+        "\r\nrow {}/{}  trees hit: {}\r\n{}\r\n",
+        toboggan.row + 1,
+        forest.height(),
+        toboggan.trees,
+        help
+    ));
+
+    frame
+}
+
+// Lets the user steer the toboggan down the forest one row per key press.
+// Returns the number of trees hit; visited cells stay marked in `forest`.
+pub fn play(forest: &mut Forest) -> io::Result<u32> {
+    let _raw = RawMode::enable()?;
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let help = "[h/a/<-] left  [l/d/->] right  [j/s/space] straight  [q] quit";
+    let mut toboggan = Toboggan::new(forest);
+    loop {
+        write!(out, "{}", render(forest, &toboggan, help))?;
+        out.flush()?;
+        let right = match read_key(&mut input)? {
+            Key::Left => -1,
+            Key::Right => 1,
+            Key::Down => 0,
+            Key::Quit => break,
+            Key::Other => continue,
+        };
+        if !toboggan.slide(forest, right, 1) {
+            break;
+        }
+    }
+
+    Ok(toboggan.trees)
+}
+
+// Walks the given slope one step per key press.
+pub fn replay(forest: &mut Forest, right: usize, down: usize) -> io::Result<u32> {
+    let _raw = RawMode::enable()?;
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let help = format!(
+        "right {} down {}  [any key] next step  [q] quit",
+        right, down
+    );
+    let mut toboggan = Toboggan::new(forest);
+    loop {
+        write!(out, "{}", render(forest, &toboggan, &help))?;
+        out.flush()?;
+        if read_key(&mut input)? == Key::Quit {
+            break;
+        }
+        if !toboggan.slide(forest, right as isize, down) {
+            break;
+        }
+    }
+
+    Ok(toboggan.trees)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slides_and_marks_visited_cells() {
+        let mut forest: Forest = "..#\n#..\n.#.".into();
+        let mut toboggan = Toboggan::new(&mut forest);
+        assert!(toboggan.slide(&mut forest, -1, 1));
+        assert_eq!(toboggan.col, 2);
+        assert!(toboggan.slide(&mut forest, 1, 1));
+        assert_eq!(toboggan.col, 0);
+        assert!(!toboggan.slide(&mut forest, 0, 1));
+        assert_eq!(toboggan.trees, 0);
+        assert!(forest.rows[0].cell(0) == Cell::BuiltOpen);
+        assert!(forest.rows[1].cell(2) == Cell::BuiltOpen);
+
+        let mut forest: Forest = "...\n.#.\n..#".into();
+        let mut toboggan = Toboggan::new(&mut forest);
+        while toboggan.slide(&mut forest, 1, 1) {}
+        assert_eq!(toboggan.trees, 2);
+        assert!(forest.rows[2].cell(2) == Cell::BuiltTree);
+    }
+
+    #[test]
+    fn reads_arrow_keys() {
+        let mut input: &[u8] = b"\x1b[Dl\x1b[Bq";
+        assert_eq!(read_key(&mut input).unwrap(), Key::Left);
+        assert_eq!(read_key(&mut input).unwrap(), Key::Right);
+        assert_eq!(read_key(&mut input).unwrap(), Key::Down);
+        assert_eq!(read_key(&mut input).unwrap(), Key::Quit);
+    }
+}
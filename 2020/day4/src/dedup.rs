@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::json;
+use crate::reader::Record;
+use crate::rules::DocumentRules;
+use crate::Passport;
+
+// Passports sharing a `pid` (and `cid`, when indexing by both). They are
+// exact duplicates when every key has the same value in each of them, and
+// conflicting otherwise.
+#[derive(Debug, PartialEq)]
+pub struct Duplicate {
+    pub pid: String,
+    pub cid: Option<String>,
+    // Index in the batch and input lines of each record.
+    pub records: Vec<(usize, RangeInclusive<usize>)>,
+    // Keys whose values differ, or that some of the records lack.
+    pub conflicts: Vec<String>,
+}
+
+impl Duplicate {
+    pub fn is_exact(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.records
+            .iter()
+            .map(|(_, lines)| format!("{}-{}", lines.start(), lines.end()))
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        let cid = match &self.cid {
+            Some(cid) => json::string(cid),
+            None => "null".to_string(),
+        };
+        let lines = self
+            .records
+            .iter()
+            .map(|(_, lines)| json::array(&[lines.start().to_string(), lines.end().to_string()]))
+            .collect::<Vec<String>>();
+        let conflicts = self
+            .conflicts
+            .iter()
+            .map(|key| json::string(key))
+            .collect::<Vec<String>>();
+        json::object(&[
+            ("pid", json::string(&self.pid)),
+            ("cid", cid),
+            ("exact", self.is_exact().to_string()),
+            ("lines", json::array(&lines)),
+            ("conflicts", json::array(&conflicts)),
+        ])
+    }
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pid:{}", self.pid)?;
+        if let Some(cid) = &self.cid {
+            write!(f, " cid:{}", cid)?;
+        }
+        if self.is_exact() {
+            write!(f, " exact duplicate")?;
+        } else {
+            write!(f, " conflicting on {}", self.conflicts.join(", "))?;
+        }
+        write!(f, " at lines {}", self.lines().join(", "))
+    }
+}
+
+// A `pid`, and the `cid` when indexing by both.
+type Id = (String, Option<String>);
+
+// The keys and values the record would be written back with, sorted so
+// field order doesn't matter.
+fn canonical(passport: &Passport, rules: &DocumentRules) -> Vec<(String, String)> {
+    let mut fields = passport
+        .fields
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !passport.is_redundant(*index, rules))
+        .map(|(_, f)| (f.key().to_string(), f.value()))
+        .collect::<Vec<(String, String)>>();
+    fields.sort();
+    fields
+}
+
+// Groups the batch by `pid`, and also by `cid` when `by_cid` is set.
+// Records without a `pid` are never duplicates. Groups come out in the order
+// their first record appears.
+pub fn find_duplicates(records: &[Record], rules: &DocumentRules, by_cid: bool) -> Vec<Duplicate> {
+    let mut groups: Vec<(Id, Vec<usize>)> = vec![];
+    let mut index: HashMap<Id, usize> = HashMap::new();
+    for (i, record) in records.iter().enumerate() {
+        let value = |key: &str| record.passport.get(key, rules).map(|f| f.value());
+        let pid = match value("pid") {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let id = (pid, if by_cid { value("cid") } else { None });
+        let group = *index.entry(id.clone()).or_insert_with(|| {
+            groups.push((id, vec![]));
+            groups.len() - 1
+        });
+        groups[group].1.push(i);
+    }
+
+    groups
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|((pid, cid), members)| {
+            let forms = members
+                .iter()
+                .map(|&i| canonical(&records[i].passport, rules))
+                .collect::<Vec<Vec<(String, String)>>>();
+            let mut conflicts = forms
+                .iter()
+                .flatten()
+                .map(|(key, _)| key.clone())
+                .filter(|key| {
+                    let values = forms
+                        .iter()
+                        .map(|form| form.iter().find(|(k, _)| k == key).map(|(_, v)| v))
+                        .collect::<Vec<Option<&String>>>();
+                    values.iter().any(|v| *v != values[0])
+                })
+                .collect::<Vec<String>>();
+            conflicts.sort();
+            conflicts.dedup();
+            Duplicate {
+                pid,
+                cid,
+                records: members
+                    .into_iter()
+                    .map(|i| (i, records[i].lines.clone()))
+                    .collect(),
+                conflicts,
+            }
+        })
+        .collect()
+}
+
+// Batch indexes of the records that repeat an earlier record in the same
+// group exactly. Conflicting records are all kept, since there's no telling
+// which one is right.
+pub fn redundant(
+    duplicates: &[Duplicate],
+    records: &[Record],
+    rules: &DocumentRules,
+) -> Vec<usize> {
+    let mut redundant = vec![];
+    for duplicate in duplicates {
+        let mut seen = vec![];
+        for (i, _) in duplicate.records.iter() {
+            let form = canonical(&records[*i].passport, rules);
+            if seen.contains(&form) {
+                redundant.push(*i);
+            } else {
+                seen.push(form);
+            }
+        }
+    }
+    redundant.sort_unstable();
+    redundant
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reader::PassportReader;
+    use crate::rules::RuleSet;
+    use crate::DEFAULT_RULES;
+
+    #[test]
+    fn finds_exact_and_conflicting_duplicates() {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let rules = rules.document("passport").unwrap();
+        let batch = "pid:1 byr:1980 ecl:brn\n\n\
+                     pid:2 byr:1990\n\n\
+                     ecl:brn byr:1980\npid:1\n\n\
+                     pid:2 byr:1991 cid:7\n\n\
+                     byr:1980";
+        let records = PassportReader::new(batch.as_bytes())
+            .collect::<Result<Vec<Record>, _>>()
+            .unwrap();
+
+        let duplicates = find_duplicates(&records, rules, false);
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(
+            duplicates[0].to_string(),
+            "pid:1 exact duplicate at lines 1-1, 5-6"
+        );
+        assert_eq!(
+            duplicates[1].to_string(),
+            "pid:2 conflicting on byr, cid at lines 3-3, 8-8"
+        );
+        assert_eq!(
+            duplicates[1].to_json(),
+            "{\"pid\":\"2\",\"cid\":null,\"exact\":false,\"lines\":[[3,3],[8,8]],\"conflicts\":[\"byr\",\"cid\"]}"
+        );
+        assert_eq!(redundant(&duplicates, &records, rules), vec![2]);
+
+        let by_cid = find_duplicates(&records, rules, true);
+        assert_eq!(by_cid.len(), 1);
+        assert_eq!(by_cid[0].cid, None);
+    }
+}
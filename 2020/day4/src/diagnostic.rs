@@ -0,0 +1,115 @@
+use std::fmt::Write;
+
+use crate::reader::Record;
+use crate::report::{Failure, ValidationReport};
+
+// Renders a report the way a compiler would, pointing at each offending
+// `key:value` in the input:
+//
+//   error: byr:1900 not between 1920 and 2002
+//    --> 12:9
+//      |
+//   12 | pid:087499704 byr:1900
+//      |               ^^^^^^^^
+//
+// Failures that aren't about a single field, such as a missing key, point at
+// the lines of the whole record instead.
+pub fn render(record: &Record, report: &ValidationReport) -> String {
+    let gutter = record.lines.end().to_string().len();
+    let mut out = String::new();
+    let failures = report.failures.iter().map(|f| ("error", f));
+    let warnings = report.warnings.iter().map(|w| ("warning", w));
+    for (level, failure) in failures.chain(warnings) {
+        render_one(&mut out, record, gutter, level, failure);
+    }
+    out
+}
+
+fn render_one(out: &mut String, record: &Record, gutter: usize, level: &str, failure: &Failure) {
+    writeln!(out, "{}: {}", level, failure).unwrap();
+    let span = match failure.field.and_then(|index| record.spans.get(index)) {
+        Some(span) => span,
+        None => {
+            let (first, last) = (record.lines.start(), record.lines.end());
+            writeln!(
+                out,
+                "{:>gutter$}--> lines {}-{}",
+                "",
+                first,
+                last,
+                gutter = gutter
+            )
+            .unwrap();
+            return;
+        }
+    };
+
+    let text = &record.source[span.line - record.lines.start()];
+    // Keep tabs so the underline lines up with the text above it.
+    let indent = text
+        .chars()
+        .take(span.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let width = text
+        .chars()
+        .skip(span.column - 1)
+        .take_while(|c| !c.is_whitespace())
+        .count();
+    let underline = "^".repeat(width);
+    writeln!(
+        out,
+        "{:>gutter$}--> {}:{}",
+        "",
+        span.line,
+        span.column,
+        gutter = gutter
+    )
+    .unwrap();
+    writeln!(out, "{:>gutter$} |", "", gutter = gutter).unwrap();
+    writeln!(out, "{:>gutter$} | {}", span.line, text, gutter = gutter).unwrap();
+    writeln!(
+        out,
+        "{:>gutter$} | {}{}",
+        "",
+        indent,
+        underline,
+        gutter = gutter
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reader::PassportReader;
+    use crate::rules::RuleSet;
+    use crate::DEFAULT_RULES;
+
+    #[test]
+    fn points_at_offending_fields() {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let rules = rules.document("passport").unwrap();
+        let input = "\n\n\n\n\n\n\n\n\niyr:2015 eyr:2025 hgt:170cm\n\thcl:#123abc ecl:brn pid:087499704 byr:1900";
+        let record = PassportReader::new(input.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        let report = record.passport.validate(rules);
+        assert_eq!(
+            render(&record, &report),
+            "error: byr:1900 not between 1920 and 2002\n  \
+             --> 11:36\n   |\n\
+             11 | \thcl:#123abc ecl:brn pid:087499704 byr:1900\n   \
+             | \t                                  ^^^^^^^^\n"
+        );
+
+        let record = PassportReader::new("hgt:170cm".as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        let report = record.passport.validate(rules);
+        assert!(render(&record, &report)
+            .starts_with("error: byr required key is missing\n --> lines 1-1\n"));
+    }
+}
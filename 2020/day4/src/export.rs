@@ -0,0 +1,206 @@
+use std::io::{self, Write};
+
+use crate::json;
+use crate::rules::DocumentRules;
+use crate::{Field, Passport};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    // One JSON object per line.
+    Json,
+    // A header row with one column per key in the rules, plus an `extra`
+    // column holding any unknown keys as `key:value` pairs.
+    Csv,
+    // The batch format we read, one record per line and a blank line between
+    // records.
+    KeyValue,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "kv" => Some(Format::KeyValue),
+            _ => None,
+        }
+    }
+}
+
+// Writes passports one at a time so a batch can be streamed through. Keys
+// come out in the order the rules list them, then unknown keys in the order
+// they were read. Repeats a `first` or `last` duplicate-key policy ignores
+// are dropped; under `reject` every occurrence is kept, so a record that
+// fails for its repeats still fails when read back. In CSV the repeats go
+// to the `extra` column.
+pub struct Exporter<'a, W: Write> {
+    out: W,
+    format: Format,
+    rules: &'a DocumentRules,
+    written: usize,
+}
+
+impl<'a, W: Write> Exporter<'a, W> {
+    pub fn new(out: W, format: Format, rules: &'a DocumentRules) -> Self {
+        Exporter {
+            out,
+            format,
+            rules,
+            written: 0,
+        }
+    }
+
+    pub fn write(&mut self, passport: &Passport) -> io::Result<()> {
+        let fields = self.ordered(passport);
+        match self.format {
+            Format::Json => {
+                let members = fields
+                    .iter()
+                    .map(|f| (f.key(), json::string(&f.value())))
+                    .collect::<Vec<(&str, String)>>();
+                writeln!(self.out, "{}", json::object(&members))?;
+            }
+            Format::Csv => {
+                if self.written == 0 {
+                    let mut header = self
+                        .rules
+                        .fields
+                        .iter()
+                        .map(|r| csv_cell(&r.key))
+                        .collect::<Vec<String>>();
+                    header.push("extra".to_string());
+                    writeln!(self.out, "{}", header.join(","))?;
+                }
+                let mut row = self
+                    .rules
+                    .fields
+                    .iter()
+                    .map(|r| match fields.iter().find(|f| f.key() == r.key) {
+                        Some(field) => csv_cell(&field.value()),
+                        None => String::new(),
+                    })
+                    .collect::<Vec<String>>();
+                let extra = fields
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, f)| {
+                        self.rules.rule(f.key()).is_none()
+                            || fields[..*i].iter().any(|g| g.key() == f.key())
+                    })
+                    .map(|(_, f)| f.to_string())
+                    .collect::<Vec<String>>();
+                row.push(csv_cell(&extra.join(" ")));
+                writeln!(self.out, "{}", row.join(","))?;
+            }
+            Format::KeyValue => {
+                if self.written > 0 {
+                    writeln!(self.out)?;
+                }
+                let fields = fields
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<Vec<String>>();
+                writeln!(self.out, "{}", fields.join(" "))?;
+            }
+        }
+        self.written += 1;
+
+        Ok(())
+    }
+
+    fn ordered<'p>(&self, passport: &'p Passport) -> Vec<&'p Field> {
+        let mut fields = passport
+            .fields
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !passport.is_redundant(*index, self.rules))
+            .map(|(_, f)| f)
+            .collect::<Vec<&Field>>();
+        // Unknown keys sort after every known one; the sort is stable so they
+        // keep their input order.
+        fields.sort_by_key(|f| {
+            self.rules
+                .fields
+                .iter()
+                .position(|r| r.key == f.key())
+                .unwrap_or(self.rules.fields.len())
+        });
+        fields
+    }
+}
+
+// Quotes cells that would otherwise break the row, doubling inner quotes.
+fn csv_cell(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::Reason;
+    use crate::rules::{DuplicateKeys, RuleSet};
+    use crate::DEFAULT_RULES;
+
+    fn export(format: Format, batch: &[&str]) -> String {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let mut rules = rules.document("passport").unwrap().clone();
+        rules.duplicate_keys = DuplicateKeys::FirstWins;
+        export_with(format, batch, &rules)
+    }
+
+    fn export_with(format: Format, batch: &[&str], rules: &DocumentRules) -> String {
+        let mut out = vec![];
+        let mut exporter = Exporter::new(&mut out, format, rules);
+        for fields in batch {
+            let mut pass = Passport::new();
+            pass.load_fields(fields);
+            exporter.write(&pass).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_each_format_in_rule_order() {
+        let batch = [
+            "pid:087499704 foo:a,\"b\" hgt:74in byr:1980 byr:1990",
+            "ecl:amb iyr:2012",
+        ];
+        assert_eq!(
+            export(Format::KeyValue, &batch),
+            "byr:1980 hgt:74in pid:087499704 foo:a,\"b\"\n\niyr:2012 ecl:amb\n"
+        );
+        assert_eq!(
+            export(Format::Json, &batch),
+            "{\"byr\":\"1980\",\"hgt\":\"74in\",\"pid\":\"087499704\",\"foo\":\"a,\\\"b\\\"\"}\n\
+             {\"iyr\":\"2012\",\"ecl\":\"amb\"}\n"
+        );
+        assert_eq!(
+            export(Format::Csv, &batch),
+            "byr,iyr,eyr,hgt,hcl,ecl,pid,cid,extra\n\
+             1980,,,74in,,,087499704,,\"foo:a,\"\"b\"\"\"\n\
+             ,2012,,,,amb,,,\n"
+        );
+    }
+
+    #[test]
+    fn keeps_rejected_repeats() {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let rules = rules.document("passport").unwrap();
+        let batch = ["byr:1937 pid:087499704 byr:1980"];
+        let kv = export_with(Format::KeyValue, &batch, rules);
+        assert_eq!(kv, "byr:1937 byr:1980 pid:087499704\n");
+        assert_eq!(
+            export_with(Format::Csv, &batch, rules).lines().nth(1),
+            Some("1937,,,,,,087499704,,byr:1980")
+        );
+
+        let mut pass = Passport::new();
+        pass.load_fields(kv.trim());
+        let reasons = pass.validate(rules).failures;
+        assert!(reasons.iter().any(|f| f.reason == Reason::DuplicateKey));
+    }
+}
@@ -0,0 +1,424 @@
+use std::fmt;
+
+use crate::json;
+use crate::report::{Failure, Reason};
+use crate::rules::{DocumentRules, FieldRule, Sample};
+
+// xorshift64*, so a seed gives the same batch on every machine without
+// pulling in a crate.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // Spread the seed over all the bits; the state must not be zero.
+        Rng((seed ^ 0x9e37_79b9_7f4a_7c15).wrapping_mul(0xbf58_476d_1ce4_e5b9) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Class {
+    Valid,
+    // Required keys left out, every value that is there passes.
+    Incomplete,
+    // Every required key there, one or two with a value that fails.
+    Invalid,
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Class::Valid => write!(f, "valid"),
+            Class::Incomplete => write!(f, "incomplete"),
+            Class::Invalid => write!(f, "invalid"),
+        }
+    }
+}
+
+// How often each class comes up, as weights: `60:20:20` makes about 60%
+// of the records valid.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mix {
+    pub valid: u32,
+    pub incomplete: u32,
+    pub invalid: u32,
+}
+
+impl Mix {
+    pub fn parse(s: &str) -> Option<Mix> {
+        let weights = s
+            .split(':')
+            .map(|w| w.trim().parse().ok())
+            .collect::<Option<Vec<u32>>>()?;
+        match weights[..] {
+            [valid, incomplete, invalid] => {
+                let total = valid.checked_add(incomplete)?.checked_add(invalid)?;
+                if total == 0 {
+                    return None;
+                }
+                Some(Mix {
+                    valid,
+                    incomplete,
+                    invalid,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Mix {
+    fn default() -> Self {
+        Mix {
+            valid: 60,
+            incomplete: 20,
+            invalid: 20,
+        }
+    }
+}
+
+// A record in the batch format and what validating it should find.
+pub struct Generated {
+    pub text: String,
+    pub class: Class,
+    pub complete: bool,
+    pub valid: bool,
+    // In the order `Passport::validate` reports them: missing keys, then
+    // fields as written, then record checks.
+    pub failures: Vec<Failure>,
+}
+
+impl Generated {
+    pub fn to_json(&self) -> String {
+        let failures = self
+            .failures
+            .iter()
+            .map(|f| f.to_json())
+            .collect::<Vec<String>>();
+        json::object(&[
+            ("class", json::string(&self.class.to_string())),
+            ("complete", self.complete.to_string()),
+            ("valid", self.valid.to_string()),
+            ("failures", json::array(&failures)),
+        ])
+    }
+}
+
+// Yields an endless batch of records for the rules, with every value taken
+// from `FieldRule::samples`, so values right at and just past the edges of
+// each rule come up often. The expected failures are worked out from the
+// samples and the record checks alone; validators registered on top of the
+// rules aren't taken into account.
+pub struct Generator<'a> {
+    rules: &'a DocumentRules,
+    rng: Rng,
+    mix: Mix,
+    samples: Vec<(&'a FieldRule, Vec<Sample>)>,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(rules: &'a DocumentRules, seed: u64, mix: Mix) -> Result<Generator<'a>, String> {
+        let samples = rules
+            .fields
+            .iter()
+            .map(|rule| (rule, rule.samples()))
+            .collect::<Vec<_>>();
+        if let Some((rule, _)) = samples
+            .iter()
+            .find(|(_, samples)| samples.iter().all(|(_, reason)| reason.is_some()))
+        {
+            return Err(format!("no passing values to generate for {}", rule.key));
+        }
+        let mut generator = Generator {
+            rules,
+            rng: Rng::new(seed),
+            mix,
+            samples,
+        };
+        // Valid records are drawn until one passes the record checks, which
+        // never happens if the ranges leave no consistent pick.
+        let mut consistent = || {
+            let fields = generator.record(Class::Valid);
+            generator.failed_checks(&fields).is_empty()
+        };
+        if mix.valid > 0 && !(0..1000).any(|_| consistent()) {
+            return Err("no passing values satisfy the record checks".to_string());
+        }
+        generator.rng = Rng::new(seed);
+        Ok(generator)
+    }
+
+    fn class(&mut self) -> Class {
+        let Mix {
+            valid,
+            incomplete,
+            invalid,
+        } = self.mix;
+        let roll = self.rng.below((valid + incomplete + invalid) as usize) as u32;
+        if roll < valid {
+            Class::Valid
+        } else if roll < valid + incomplete {
+            Class::Incomplete
+        } else {
+            Class::Invalid
+        }
+    }
+
+    // Passing values for every required key and about half the others.
+    fn passing(&mut self) -> Vec<(usize, String)> {
+        let mut fields = vec![];
+        for (index, (rule, samples)) in self.samples.iter().enumerate() {
+            if !rule.required && self.rng.below(2) == 0 {
+                continue;
+            }
+            let passing = samples
+                .iter()
+                .filter(|(_, reason)| reason.is_none())
+                .collect::<Vec<_>>();
+            fields.push((index, self.rng.pick(&passing).0.clone()));
+        }
+        fields
+    }
+
+    fn record(&mut self, class: Class) -> Vec<(usize, String, Option<Reason>)> {
+        let mut fields = self
+            .passing()
+            .into_iter()
+            .map(|(index, value)| (index, value, None))
+            .collect::<Vec<_>>();
+        let samples = &self.samples;
+        match class {
+            Class::Valid => {}
+            Class::Incomplete => {
+                let mut required = (0..fields.len())
+                    .filter(|&i| samples[fields[i].0].0.required)
+                    .collect::<Vec<usize>>();
+                self.rng.shuffle(&mut required);
+                let dropped = (1 + self.rng.below(2)).min(required.len());
+                required.truncate(dropped);
+                required.sort_unstable();
+                for i in required.into_iter().rev() {
+                    fields.remove(i);
+                }
+            }
+            Class::Invalid => {
+                let mut breakable = (0..fields.len())
+                    .filter(|&i| samples[fields[i].0].1.iter().any(|(_, r)| r.is_some()))
+                    .collect::<Vec<usize>>();
+                self.rng.shuffle(&mut breakable);
+                breakable.truncate(1 + self.rng.below(2));
+                for i in breakable {
+                    let failing = samples[fields[i].0]
+                        .1
+                        .iter()
+                        .filter(|(_, reason)| reason.is_some())
+                        .collect::<Vec<_>>();
+                    let (value, reason) = self.rng.pick(&failing);
+                    fields[i].1 = value.clone();
+                    fields[i].2 = reason.clone();
+                }
+            }
+        }
+        fields
+    }
+
+    fn failed_checks(&self, fields: &[(usize, String, Option<Reason>)]) -> Vec<Failure> {
+        let value = |key: &str| {
+            fields
+                .iter()
+                .find(|(index, _, _)| self.samples[*index].0.key == key)
+                .map(|(_, value, _)| value)
+        };
+        self.rules
+            .checks
+            .iter()
+            .filter(|check| {
+                // Like `Passport::validate`, keys whose value fails are left out.
+                let number = |key: &str| {
+                    let passing = fields.iter().any(|(index, _, reason)| {
+                        self.samples[*index].0.key == key && reason.is_none()
+                    });
+                    value(key).filter(|_| passing)?.parse::<i64>().ok()
+                };
+                check.holds(number, self.rules.reference_year) == Some(false)
+            })
+            .map(|check| Failure {
+                key: check.key.clone(),
+                value: value(&check.key).cloned(),
+                reason: Reason::Inconsistent {
+                    check: check.to_string(),
+                },
+                field: None,
+            })
+            .collect()
+    }
+}
+
+impl Iterator for Generator<'_> {
+    type Item = Generated;
+
+    fn next(&mut self) -> Option<Generated> {
+        let class = self.class();
+        let mut fields = self.record(class);
+        // The ranges in a rule file may not leave every pick consistent, so
+        // valid records are drawn again until they pass the record checks,
+        // which `Generator::new` made sure they can.
+        while class == Class::Valid && !self.failed_checks(&fields).is_empty() {
+            fields = self.record(class);
+        }
+        self.rng.shuffle(&mut fields);
+
+        let mut failures = vec![];
+        for (rule, _) in self.samples.iter() {
+            let present = fields
+                .iter()
+                .any(|(index, _, _)| self.samples[*index].0.key == rule.key);
+            if rule.required && !present {
+                failures.push(Failure {
+                    key: rule.key.clone(),
+                    value: None,
+                    reason: Reason::Missing,
+                    field: None,
+                });
+            }
+        }
+        let complete = failures.is_empty();
+        for (index, value, reason) in fields.iter() {
+            if let Some(reason) = reason {
+                failures.push(Failure {
+                    key: self.samples[*index].0.key.clone(),
+                    value: Some(value.clone()),
+                    reason: reason.clone(),
+                    field: None,
+                });
+            }
+        }
+        failures.extend(self.failed_checks(&fields));
+
+        // A space or, now and then, a line break between fields.
+        let mut text = String::new();
+        for (i, (index, value, _)) in fields.iter().enumerate() {
+            if i > 0 {
+                text.push(if self.rng.below(4) == 0 { '\n' } else { ' ' });
+            }
+            text.push_str(&format!("{}:{}", self.samples[*index].0.key, value));
+        }
+
+        Some(Generated {
+            text,
+            class,
+            complete,
+            valid: failures.is_empty(),
+            failures,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reader::PassportReader;
+    use crate::rules::RuleSet;
+    use crate::DEFAULT_RULES;
+
+    #[test]
+    fn generated_batches_validate_as_expected() {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let mut rules = rules.document("passport").unwrap().clone();
+        let mut classes = [0; 3];
+        let mut birth_years = vec![];
+        for seed in 0..20 {
+            // Every other batch has a reference year, which valid records
+            // have to pick an expiry year to pass.
+            rules.reference_year = if seed % 2 == 0 { None } else { Some(2025) };
+            let batch = Generator::new(&rules, seed, Mix::default())
+                .unwrap()
+                .take(200)
+                .collect::<Vec<Generated>>();
+            let text = batch
+                .iter()
+                .map(|g| g.text.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n\n");
+            let records = PassportReader::new(text.as_bytes())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(records.len(), batch.len());
+
+            for (generated, record) in batch.iter().zip(records.iter()) {
+                let pass = &record.passport;
+                let report = pass.validate(&rules);
+                let found = report
+                    .failures
+                    .iter()
+                    .map(|f| Failure {
+                        field: None,
+                        ..f.clone()
+                    })
+                    .collect::<Vec<Failure>>();
+                assert_eq!(found, generated.failures, "{}", generated.text);
+                assert_eq!(pass.is_complete(&rules), generated.complete);
+                assert_eq!(pass.is_valid(&rules), generated.valid);
+                match generated.class {
+                    Class::Valid => classes[0] += 1,
+                    Class::Incomplete => {
+                        assert!(!generated.complete);
+                        classes[1] += 1
+                    }
+                    Class::Invalid => classes[2] += 1,
+                }
+                assert_eq!(generated.class == Class::Valid, generated.valid);
+                if let Some(byr) = pass.get("byr", &rules) {
+                    birth_years.push(byr.value());
+                }
+            }
+        }
+
+        // 4000 records in about a 60:20:20 mix.
+        assert!((2200..2600).contains(&classes[0]), "{:?}", classes);
+        assert!((600..1000).contains(&classes[1]), "{:?}", classes);
+        assert!((600..1000).contains(&classes[2]), "{:?}", classes);
+        for year in ["1919", "1920", "2002", "2003"].iter() {
+            assert!(birth_years.iter().any(|y| y == year), "{}", year);
+        }
+
+        assert_eq!(Mix::parse("1:0:3").unwrap().invalid, 3);
+        assert!(Mix::parse("0:0:0").is_none());
+        assert!(Mix::parse("60:40").is_none());
+        assert!(Mix::parse("4294967295:1:0").is_none());
+        let patterned = RuleSet::parse("[p]\npid = text required pattern=[0-9]{9}").unwrap();
+        assert!(Generator::new(patterned.document("p").unwrap(), 1, Mix::default()).is_err());
+        let backwards = RuleSet::parse(
+            "[p]\nbyr = number required range=1920..2002\n\
+             iyr = number required range=2010..2020\n@check byr > iyr",
+        )
+        .unwrap();
+        let backwards = backwards.document("p").unwrap();
+        assert!(Generator::new(backwards, 1, Mix::default()).is_err());
+        let never_valid = Mix::parse("0:1:1").unwrap();
+        let batch = Generator::new(backwards, 1, never_valid).unwrap().take(50);
+        assert!(batch
+            .into_iter()
+            .all(|g| !g.valid && g.class != Class::Valid));
+    }
+}
@@ -0,0 +1,46 @@
+// Just enough JSON output for reports and exports, without pulling in a
+// dependency.
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn object(members: &[(&str, String)]) -> String {
+    let members = members
+        .iter()
+        .map(|(key, value)| format!("{}:{}", string(key), value))
+        .collect::<Vec<String>>();
+    format!("{{{}}}", members.join(","))
+}
+
+pub fn array(items: &[String]) -> String {
+    format!("[{}]", items.join(","))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+        assert_eq!(
+            object(&[("k", string("v")), ("n", "1".to_string())]),
+            "{\"k\":\"v\",\"n\":1}"
+        );
+        assert_eq!(array(&[]), "[]");
+    }
+}
@@ -1,82 +1,297 @@
-use std::io::{self, BufRead};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader, Write};
+use std::thread;
+
+mod dedup;
+mod diagnostic;
+mod export;
+mod generate;
+mod json;
+mod pattern;
+mod pipeline;
+mod policy;
+mod query;
+mod reader;
+mod redact;
+mod report;
+mod rules;
+mod stats;
+mod suggest;
+mod typed;
+mod validator;
+
+use dedup::{find_duplicates, redundant};
+use export::{Exporter, Format};
+use generate::{Class, Generator, Mix};
+use policy::Policy;
+use query::Query;
+use reader::{PassportReader, Record};
+use report::{Reason, ValidationReport};
+use rules::{DocumentRules, DuplicateKeys, RecordCheck, RuleSet, UnknownKeys};
+use stats::Stats;
+use suggest::Confidence;
+use typed::TypedPassport;
+
+const DEFAULT_RULES: &str = include_str!("../res/passport.rules");
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 struct Year(u32);
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum DimensionType {
+    Mm,
     Cm,
+    M,
     In,
+    // Feet and inches, written `5'11"`.
+    FtIn,
     Unknown,
 }
 
+// A length in millionths of its unit, so `1.80m` and `5'11"` (71 inches)
+// are kept exactly and print back in the notation they were written in.
 #[derive(Copy, Clone, Debug, PartialEq)]
-struct Dimension(u32, DimensionType);
+struct Dimension(u64, DimensionType);
 
-impl From<&str> for Dimension {
-    fn from(s: &str) -> Self {
-        let cm = "cm";
-        let _in = "in";
+// Heights are compared in micrometres, which every unit converts to
+// exactly.
+const MICROS: u64 = 1_000_000;
+
+impl DimensionType {
+    fn from_suffix(s: &str) -> Option<DimensionType> {
+        match s {
+            "mm" => Some(DimensionType::Mm),
+            "cm" => Some(DimensionType::Cm),
+            "m" => Some(DimensionType::M),
+            "in" => Some(DimensionType::In),
+            "ftin" => Some(DimensionType::FtIn),
+            _ => None,
+        }
+    }
+
+    // Millimetres, centimetres and metres, as opposed to inches and feet.
+    fn metric(self) -> bool {
+        matches!(
+            self,
+            DimensionType::Mm | DimensionType::Cm | DimensionType::M
+        )
+    }
+
+    fn micrometres_per_unit(self) -> Option<u64> {
+        match self {
+            DimensionType::Mm => Some(1_000),
+            DimensionType::Cm => Some(10_000),
+            DimensionType::M => Some(1_000_000),
+            DimensionType::In | DimensionType::FtIn => Some(25_400),
+            DimensionType::Unknown => None,
+        }
+    }
+}
+
+// Parses a non-negative decimal with up to six fractional digits into
+// millionths.
+fn parse_decimal(s: &str) -> Option<u64> {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if int.is_empty() || !digits(int) || !digits(frac) || frac.len() > 6 {
+        return None;
+    }
+    let frac = format!("{:0<6}", frac).parse::<u64>().ok()?;
+    int.parse::<u64>()
+        .ok()?
+        .checked_mul(MICROS)?
+        .checked_add(frac)
+}
+
+fn format_decimal(millionths: u64) -> String {
+    let (int, frac) = (millionths / MICROS, millionths % MICROS);
+    if frac == 0 {
+        return int.to_string();
+    }
+    let frac = format!("{:06}", frac);
+    format!("{}.{}", int, frac.trim_end_matches('0'))
+}
+
+impl Dimension {
+    fn parse(s: &str) -> Option<Dimension> {
         let s = s.trim();
-        let dim = || -> u32 {
-            let num_str = if s.ends_with(cm) || s.ends_with(_in) {
-                &s[..(s.len() - 2)]
-            } else {
-                s
+        if let Some((feet, inches)) = s.split_once('\'') {
+            let feet = parse_decimal(feet)?;
+            let inches = match inches.strip_suffix('"').unwrap_or(inches) {
+                "" => 0,
+                inches => parse_decimal(inches)?,
             };
-            num_str.parse::<u32>().expect("Invalid dimension")
-        }();
+            return Some(Dimension(feet * 12 + inches, DimensionType::FtIn));
+        }
 
-        if s.ends_with(cm) {
-            Dimension(dim, DimensionType::Cm)
-        } else if s.ends_with(_in) {
-            Dimension(dim, DimensionType::In)
-        } else {
-            Dimension(dim, DimensionType::Unknown)
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (num_str, suffix) = s.split_at(split);
+        let dim = parse_decimal(num_str)?;
+        if suffix.is_empty() {
+            return Some(Dimension(dim, DimensionType::Unknown));
+        }
+        match DimensionType::from_suffix(suffix) {
+            Some(DimensionType::FtIn) | None => None,
+            Some(unit) => Some(Dimension(dim, unit)),
         }
     }
+
+    // The length in the canonical unit, or `None` without a unit.
+    fn micrometres(&self) -> Option<u64> {
+        let per_unit = self.1.micrometres_per_unit()?;
+        Some((self.0 as u128 * per_unit as u128 / MICROS as u128) as u64)
+    }
+
+    fn convert(&self, unit: DimensionType) -> Option<Dimension> {
+        let micrometres = self.micrometres()? as u128;
+        let per_unit = unit.micrometres_per_unit()? as u128;
+        Some(Dimension(
+            (micrometres * MICROS as u128 / per_unit) as u64,
+            unit,
+        ))
+    }
+
+    // Centimetres are what people read most easily.
+    fn normalized(&self) -> Option<Dimension> {
+        self.convert(DimensionType::Cm)
+    }
+}
+
+impl From<&str> for Dimension {
+    fn from(s: &str) -> Self {
+        Dimension::parse(s).expect("Invalid dimension")
+    }
 }
 
-#[derive(Debug, PartialEq)]
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let suffix = match self.1 {
+            DimensionType::Mm => "mm",
+            DimensionType::Cm => "cm",
+            DimensionType::M => "m",
+            DimensionType::In => "in",
+            DimensionType::FtIn => {
+                let feet = self.0 / (12 * MICROS);
+                let inches = self.0 - feet * 12 * MICROS;
+                return write!(f, "{}'{}\"", feet, format_decimal(inches));
+            }
+            DimensionType::Unknown => "",
+        };
+        write!(f, "{}{}", format_decimal(self.0), suffix)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 struct ID(String);
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 struct HexColor(String);
 
-impl HexColor {
-    fn is_valid(&self) -> bool {
-        if self.0.len() != 7 {
-            return false;
-        }
+// Which letter case the digits `a`-`f` may be written in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ColorCase {
+    Lower,
+    Upper,
+    Any,
+}
 
-        let mut chars = self.0.chars();
-        if chars.nth(0).unwrap() != '#' {
-            return false;
+impl ColorCase {
+    fn parse(s: &str) -> Option<ColorCase> {
+        match s {
+            "lower" => Some(ColorCase::Lower),
+            "upper" => Some(ColorCase::Upper),
+            "any" => Some(ColorCase::Any),
+            _ => None,
         }
+    }
+}
 
-        for c in chars.skip(1) {
-            if !c.is_numeric() && (c < 'a' || c > 'f') {
-                println!("invalid hexcolor: {}", self.0);
-                return false;
-            }
+#[derive(Clone, Debug, PartialEq)]
+enum ColorError {
+    MissingHash,
+    // The number of digits after the `#`.
+    WrongLength(usize),
+    NotHex(char),
+    WrongCase(char),
+}
+
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColorError::MissingHash => write!(f, "does not start with #"),
+            ColorError::WrongLength(n) => write!(f, "has {} hex digits", n),
+            ColorError::NotHex(c) => write!(f, "{} is not a hex digit", c),
+            ColorError::WrongCase(c) => write!(f, "{} is in the wrong case", c),
         }
+    }
+}
 
-        true
+// A parsed color, printed in the canonical `#rrggbb` lowercase form.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Rgb {
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
     }
 }
 
-#[derive(Debug, PartialEq)]
-struct Color(String);
+impl From<Rgb> for HexColor {
+    fn from(rgb: Rgb) -> Self {
+        HexColor(rgb.to_string())
+    }
+}
 
-impl Color {
-    fn is_valid(&self) -> bool {
-        ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"]
-            .iter()
-            .find(|&&c| self.0 == c)
-            .is_some()
+impl HexColor {
+    // Accepts `#rrggbb`, and `#rgb` when `short` is set, with the hex letters
+    // in the given case.
+    fn parse(&self, case: ColorCase, short: bool) -> Result<Rgb, ColorError> {
+        let digits = self.0.strip_prefix('#').ok_or(ColorError::MissingHash)?;
+        let count = digits.chars().count();
+        if count != 6 && !(short && count == 3) {
+            return Err(ColorError::WrongLength(count));
+        }
+
+        let mut values = vec![];
+        for c in digits.chars() {
+            let value = c.to_digit(16).ok_or(ColorError::NotHex(c))?;
+            let wrong_case = match case {
+                ColorCase::Lower => c.is_ascii_uppercase(),
+                ColorCase::Upper => c.is_ascii_lowercase(),
+                ColorCase::Any => false,
+            };
+            if wrong_case {
+                return Err(ColorError::WrongCase(c));
+            }
+            values.push(value as u8);
+        }
+
+        let channel = |i: usize| {
+            if count == 3 {
+                values[i] * 17
+            } else {
+                values[2 * i] * 16 + values[2 * i + 1]
+            }
+        };
+        Ok(Rgb {
+            r: channel(0),
+            g: channel(1),
+            b: channel(2),
+        })
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+struct Color(String);
+
+#[derive(Clone, Debug, PartialEq)]
 enum Field {
     BirthYear(Year),
     IssueYear(Year),
@@ -86,56 +301,88 @@ enum Field {
     EyeColor(Color),
     PassportID(ID),
     CountryID(ID),
+    Unknown { key: String, value: String },
 }
 
+// A value that doesn't parse as its key's type, such as `byr:19x7` or
+// `hgt:6ft`, is kept as text under its key so the key's rule can report it.
 impl From<&str> for Field {
     fn from(s: &str) -> Self {
-        let splitted = s.split(":").collect::<Vec<&str>>();
-        let lhs = splitted[0].trim();
-        let rhs = splitted[1].trim();
-        let rhs_as_int = || {
+        let (lhs, rhs) = s.split_once(':').unwrap_or((s, ""));
+        let lhs = lhs.trim();
+        let rhs = rhs.trim();
+        let as_text = || Field::Unknown {
+            key: lhs.to_string(),
+            value: rhs.to_string(),
+        };
+        let year = |field: fn(Year) -> Field| {
             rhs.parse::<u32>()
-                .expect("Couldn't parse string to integer.")
+                .map_or_else(|_| as_text(), |year| field(Year(year)))
         };
 
         match lhs {
-            "byr" => Field::BirthYear(Year(rhs_as_int())),
-            "iyr" => Field::IssueYear(Year(rhs_as_int())),
-            "eyr" => Field::ExpirationYear(Year(rhs_as_int())),
-            "hgt" => Field::Height(rhs.into()),
+            "byr" => year(Field::BirthYear),
+            "iyr" => year(Field::IssueYear),
+            "eyr" => year(Field::ExpirationYear),
+            "hgt" => Dimension::parse(rhs).map_or_else(as_text, Field::Height),
             "hcl" => Field::HairColor(HexColor(rhs.to_string())),
             "ecl" => Field::EyeColor(Color(rhs.to_string())),
             "pid" => Field::PassportID(ID(rhs.to_string())),
             "cid" => Field::CountryID(ID(rhs.to_string())),
-            _ => unreachable!(),
+            _ => Field::Unknown {
+                key: lhs.to_string(),
+                value: rhs.to_string(),
+            },
         }
     }
 }
 
 impl Field {
-    fn is_cid(&self) -> bool {
+    fn key(&self) -> &str {
         match self {
-            Field::CountryID(..) => true,
-            _ => false,
+            Field::BirthYear(..) => "byr",
+            Field::IssueYear(..) => "iyr",
+            Field::ExpirationYear(..) => "eyr",
+            Field::Height(..) => "hgt",
+            Field::HairColor(..) => "hcl",
+            Field::EyeColor(..) => "ecl",
+            Field::PassportID(..) => "pid",
+            Field::CountryID(..) => "cid",
+            Field::Unknown { key, .. } => key,
         }
     }
 
-    fn is_valid(&self) -> bool {
+    fn value(&self) -> String {
         match self {
-            Field::BirthYear(year) => year.0 >= 1920 && year.0 <= 2002,
-            Field::IssueYear(year) => year.0 >= 2010 && year.0 <= 2020,
-            Field::ExpirationYear(year) => year.0 >= 2020 && year.0 <= 2030,
-            Field::Height(dimension) => match dimension.1 {
-                DimensionType::Cm => dimension.0 >= 150 && dimension.0 <= 193,
-                DimensionType::In => dimension.0 >= 59 && dimension.0 <= 76,
-                DimensionType::Unknown => false,
-            },
-            Field::HairColor(color) => color.is_valid(),
-            Field::EyeColor(color) => color.is_valid(),
-            Field::PassportID(id) => id.0.len() == 9,
-            Field::CountryID(id) => true,
+            Field::BirthYear(year) | Field::IssueYear(year) | Field::ExpirationYear(year) => {
+                year.0.to_string()
+            }
+            Field::Height(dimension) => dimension.to_string(),
+            Field::HairColor(color) => color.0.clone(),
+            Field::EyeColor(color) => color.0.clone(),
+            Field::PassportID(id) | Field::CountryID(id) => id.0.clone(),
+            Field::Unknown { value, .. } => value.clone(),
         }
     }
+
+    // Keys without a rule are unknown to the document; whether that rejects
+    // the record is up to its `unknown_keys` policy.
+    fn check(&self, rules: &DocumentRules) -> Result<(), Reason> {
+        if rules.rule(self.key()).is_none() && rules.unknown_keys == UnknownKeys::Reject {
+            return Err(Reason::UnknownKey);
+        }
+        let value = self.value();
+        rules
+            .validators(self.key())
+            .iter()
+            .try_for_each(|validator| validator.check(&value))
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.key(), self.value())
+    }
 }
 
 #[derive(Debug)]
@@ -149,54 +396,525 @@ impl Passport {
     }
 
     pub fn load_fields(&mut self, s: &str) {
-        for field_str in s.split(" ") {
+        for field_str in s.split_whitespace() {
             self.fields.push(field_str.into());
         }
     }
 
-    // `is_complete()` solves part1 of the problem.
-    pub fn is_complete(&self) -> bool {
-        if self.fields.len() == 8 {
-            return true;
-        } else if self.fields.len() == 7 {
-            return self.fields.iter().filter(|f| f.is_cid()).next().is_none();
+    // `is_complete()` solves part1 of the problem. Repeating a key doesn't
+    // make up for a missing one.
+    pub fn is_complete(&self, rules: &DocumentRules) -> bool {
+        rules
+            .required_keys()
+            .all(|key| self.fields.iter().any(|f| f.key() == key))
+    }
+
+    // Whether the field at `index` is a repeated key that doesn't count under
+    // the document's conflict policy. With `Reject` the first occurrence
+    // counts and the repeats fail the record.
+    fn is_shadowed(&self, index: usize, rules: &DocumentRules) -> bool {
+        let key = self.fields[index].key();
+        let mut same_key = self
+            .fields
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.key() == key)
+            .map(|(i, _)| i);
+        match rules.duplicate_keys {
+            DuplicateKeys::LastWins => same_key.next_back() != Some(index),
+            DuplicateKeys::FirstWins | DuplicateKeys::Reject => same_key.next() != Some(index),
         }
+    }
+
+    // Whether the field at `index` can be left out when the record is written
+    // back. Only repeats a `FirstWins` or `LastWins` policy ignores can; with
+    // `Reject` the repeats are what fail the record, so they have to stay.
+    fn is_redundant(&self, index: usize, rules: &DocumentRules) -> bool {
+        rules.duplicate_keys != DuplicateKeys::Reject && self.is_shadowed(index, rules)
+    }
 
-        false
+    // Where the field that counts for `key` under the duplicate-key policy
+    // is.
+    fn position(&self, key: &str, rules: &DocumentRules) -> Option<usize> {
+        (0..self.fields.len())
+            .find(|&index| self.fields[index].key() == key && !self.is_shadowed(index, rules))
     }
 
-    pub fn is_valid(&self) -> bool {
-        self.is_complete()
-            && self.fields.iter().fold(true, |acc, f| -> bool {
-                if !f.is_valid() {
-                    println!("{:?}", f);
+    fn get(&self, key: &str, rules: &DocumentRules) -> Option<&Field> {
+        self.position(key, rules).map(|index| &self.fields[index])
+    }
+
+    // Checks on a key whose value already fails its own rule are skipped, so
+    // `eyr:1972` is reported as out of range and not also as before `iyr`.
+    fn failed_checks<'r>(&self, rules: &'r DocumentRules) -> Vec<&'r RecordCheck> {
+        let number = |key: &str| {
+            let field = self.get(key, rules)?;
+            field.check(rules).ok()?;
+            field.value().parse::<i64>().ok()
+        };
+        rules
+            .checks
+            .iter()
+            .filter(|check| check.holds(number, rules.reference_year) == Some(false))
+            .collect()
+    }
+
+    pub fn validate(&self, rules: &DocumentRules) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        for key in rules.required_keys() {
+            if !self.fields.iter().any(|f| f.key() == key) {
+                report.push(key, None, Reason::Missing);
+            }
+        }
+        for (index, field) in self.fields.iter().enumerate() {
+            if self.is_shadowed(index, rules) {
+                if rules.duplicate_keys == DuplicateKeys::Reject {
+                    report.push_field(index, field, Reason::DuplicateKey);
+                } else {
+                    report.warn_field(index, field, Reason::DuplicateKey);
+                }
+            } else if let Err(reason) = field.check(rules) {
+                report.push_field(index, field, reason);
+            } else if rules.rule(field.key()).is_none() && rules.unknown_keys == UnknownKeys::Warn {
+                report.warn_field(index, field, Reason::UnknownKey);
+            }
+        }
+        for check in self.failed_checks(rules) {
+            // A check only fails when its key is there.
+            let index = self.position(&check.key, rules).unwrap();
+            let reason = Reason::Inconsistent {
+                check: check.to_string(),
+            };
+            report.push_field(index, &self.fields[index], reason);
+        }
+
+        report
+    }
+
+    pub fn is_valid(&self, rules: &DocumentRules) -> bool {
+        self.is_complete(rules)
+            && self.fields.iter().enumerate().all(|(index, f)| {
+                if self.is_shadowed(index, rules) {
+                    rules.duplicate_keys != DuplicateKeys::Reject
+                } else {
+                    f.check(rules).is_ok()
                 }
-                acc && f.is_valid()
             })
+            && self.failed_checks(rules).is_empty()
     }
+}
 
-    pub fn reset(&mut self) {
-        self.fields.clear();
+// Writes the record back in the batch format, one line per record.
+impl fmt::Display for Passport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| field.to_string())
+            .collect::<Vec<String>>();
+        write!(f, "{}", fields.join(" "))
     }
 }
 
-fn main() {
+// What a worker finds out about one record; the rest happens in input order
+// on the main thread.
+struct Verdict {
+    record: Record,
+    matches: bool,
+    // One entry per policy, empty when the record doesn't match the query.
+    accepted: Vec<bool>,
+    report: Option<ValidationReport>,
+}
+
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .map(|i| args.get(i + 1).expect("Missing flag value").as_str())
+}
+
+// `day4 [flags] duplicates [--by-cid] [--dedup]` looks for passports that
+// share a `pid` (and `cid`) anywhere in the batch. With `--dedup` the batch is
+// written back without exact repeats, as `kv` unless `--export` says
+// otherwise, and the findings go to stderr.
+fn report_duplicates(args: &[String], rules: &DocumentRules) {
+    let stdin = io::stdin();
+    let records = PassportReader::new(stdin.lock())
+        .collect::<io::Result<Vec<Record>>>()
+        .expect("Could not read line from standard in");
+    let found = find_duplicates(&records, rules, args.iter().any(|a| a == "--by-cid"));
+    let exact = found.iter().filter(|d| d.is_exact()).count();
+    let mut lines = found
+        .iter()
+        .map(|d| redact::duplicate(d, rules))
+        .map(|d| match flag(args, "--report") {
+            Some("json") => d.to_json(),
+            _ => d.to_string(),
+        })
+        .collect::<Vec<String>>();
+    lines.push(format!(
+        "Duplicated ids: {} ({} exact, {} conflicting)",
+        found.len(),
+        exact,
+        found.len() - exact
+    ));
+
+    if !args.iter().any(|a| a == "--dedup") {
+        for line in lines {
+            println!("{}", line);
+        }
+        return;
+    }
+    for line in lines {
+        eprintln!("{}", line);
+    }
+    let skip = redundant(&found, &records, rules);
+    let format = flag(args, "--export").map_or(Format::KeyValue, |format| {
+        Format::parse(format).expect("Expected json, csv or kv")
+    });
+    let stdout = io::stdout();
+    let mut exporter = Exporter::new(stdout.lock(), format, rules);
+    for (_, record) in records
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| skip.binary_search(i).is_err())
+    {
+        exporter
+            .write(&redact::passport(&record.passport, rules))
+            .expect("Could not write to standard out");
+    }
+}
+
+// `day4 [flags] suggest [--apply] [--min-confidence high|medium|low]` lists
+// corrections for failing fields, by input line and column. With `--apply`
+// the batch is written back with the corrections at the given confidence
+// (`high` unless given) applied, as `kv` unless `--export` says otherwise,
+// and the suggestions go to stderr.
+fn suggest_fixes(args: &[String], rules: &DocumentRules) {
+    let min = flag(args, "--min-confidence").map_or(Confidence::High, |level| {
+        Confidence::parse(level).expect("Expected high, medium or low")
+    });
+    let apply = args.iter().any(|a| a == "--apply");
+    let format = flag(args, "--export").map_or(Format::KeyValue, |format| {
+        Format::parse(format).expect("Expected json, csv or kv")
+    });
+    let stdout = io::stdout();
+    let mut exporter = Exporter::new(stdout.lock(), format, rules);
+
+    let (mut suggested, mut applied) = (0, 0);
     let stdin = io::stdin();
-    let mut pass = Passport::new();
+    for record in PassportReader::new(stdin.lock()) {
+        let mut record = record.expect("Could not read line from standard in");
+        let report = record.passport.validate(rules);
+        let suggestions = suggest::suggest(&report, rules);
+        for suggestion in suggestions.iter() {
+            let span = &record.spans[suggestion.field];
+            let suggestion = redact::suggestion(suggestion, rules);
+            let line = format!("{}:{}: {}", span.line, span.column, suggestion);
+            if apply {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+        suggested += suggestions.len();
+        if apply {
+            applied += suggest::apply(&mut record.passport, &suggestions, min);
+            exporter
+                .write(&redact::passport(&record.passport, rules))
+                .expect("Could not write to standard out");
+        }
+    }
+
+    if apply {
+        eprintln!("Suggestions: {}, applied: {}", suggested, applied);
+    } else {
+        println!("Suggestions: {}", suggested);
+    }
+}
+
+// `day4 [flags] generate [--count N] [--seed N] [--mix VALID:INCOMPLETE:INVALID]
+// [--expected PATH]` writes a synthetic batch for the rules, 100 records in a
+// `60:20:20` mix from seed 1 unless given. `--expected PATH` gets what
+// validating each record should find, one JSON object per line. Feeding a
+// large batch back in is how we time the reader and the worker pool.
+fn generate_batch(args: &[String], rules: &DocumentRules) {
+    let count = flag(args, "--count").map_or(100, |n| n.parse().expect("Expected a count"));
+    let seed = flag(args, "--seed").map_or(1, |n| n.parse().expect("Expected a seed"));
+    let mix = flag(args, "--mix").map_or(Mix::default(), |mix| {
+        Mix::parse(mix).expect("Expected weights like 60:20:20")
+    });
+    let generator = Generator::new(rules, seed, mix)
+        .unwrap_or_else(|e| panic!("Cannot generate passports, {}", e));
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut expected = vec![];
+    let mut classes = [0u32; 3];
+    for (index, generated) in generator.take(count).enumerate() {
+        if index > 0 {
+            writeln!(out).expect("Could not write to standard out");
+        }
+        writeln!(out, "{}", generated.text).expect("Could not write to standard out");
+        expected.push(generated.to_json());
+        classes[generated.class as usize] += 1;
+    }
+    if let Some(path) = flag(args, "--expected") {
+        expected.push(String::new());
+        fs::write(path, expected.join("\n")).expect("Could not write expected verdicts");
+    }
+    eprintln!(
+        "Generated passports: {} ({} {}, {} {}, {} {})",
+        count,
+        classes[0],
+        Class::Valid,
+        classes[1],
+        Class::Incomplete,
+        classes[2],
+        Class::Invalid
+    );
+}
+
+// Builds a passport from `--byr 1937 --hgt 183cm ...` style arguments; any
+// other `--key value` pair is kept as an extra key.
+fn new_passport(args: &[String]) -> TypedPassport {
+    let mut builder = TypedPassport::builder();
+    for pair in args.chunks(2) {
+        let name = pair[0].as_str();
+        let value = pair.get(1).expect("Missing flag value").as_str();
+        let year = value.parse::<u32>();
+        builder = match (name, year) {
+            ("--byr", Ok(year)) => builder.birth_year(year),
+            ("--iyr", Ok(year)) => builder.issue_year(year),
+            ("--eyr", Ok(year)) => builder.expiration_year(year),
+            // Years that aren't numbers are kept as given for the report.
+            ("--byr", _) | ("--iyr", _) | ("--eyr", _) => builder.extra(&name[2..], value),
+            _ => match name {
+                "--hgt" => builder.height(value),
+                "--hcl" => builder.hair_color(value),
+                "--ecl" => builder.eye_color(value),
+                "--pid" => builder.passport_id(value),
+                "--cid" => builder.country_id(value),
+                name => builder.extra(name.trim_start_matches('-'), value),
+            },
+        };
+    }
+    builder.build()
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let rules = match flag(&args, "--rules") {
+        Some(path) => fs::read_to_string(path).expect("Could not read rules file"),
+        None => DEFAULT_RULES.to_string(),
+    };
+    let rules = RuleSet::parse(&rules).unwrap_or_else(|e| panic!("Invalid rules file, {}", e));
+    let document = flag(&args, "--document").unwrap_or("passport");
+    let mut rules = rules
+        .document(document)
+        .cloned()
+        .unwrap_or_else(|| panic!("No rules for document type {}", document));
+    if let Some(policy) = flag(&args, "--unknown-keys") {
+        rules.unknown_keys = UnknownKeys::parse(policy).expect("Expected allow, warn or reject");
+    }
+    if let Some(policy) = flag(&args, "--duplicate-keys") {
+        rules.duplicate_keys =
+            DuplicateKeys::parse(policy).expect("Expected first, last or reject");
+    }
+    if let Some(year) = flag(&args, "--reference-year") {
+        rules.reference_year = Some(year.parse().expect("Expected a year"));
+    }
+    // `--redact` hides the values of keys with a `@redact` rule in
+    // everything written out: reports, diagnostics, exports and the
+    // subcommands. Without it the `@redact` rules are dropped, and redacting
+    // leaves values as they are. Counts and statistics always see the real
+    // values.
+    if !args.iter().any(|a| a == "--redact") {
+        rules.redactions.clear();
+    }
+    let rules = &rules;
+
+    // `day4 [flags] new --byr 1937 ...` prints a single passport and its
+    // report instead of reading a batch.
+    if let Some(pos) = args.iter().position(|a| a == "new") {
+        let pass: Passport = new_passport(&args[pos + 1..]).into();
+        println!("{}", redact::passport(&pass, rules));
+        print!("{}", redact::report(&pass.validate(rules), rules));
+        return;
+    }
+
+    if args.iter().any(|a| a == "generate") {
+        generate_batch(&args, rules);
+        return;
+    }
+
+    if args.iter().any(|a| a == "suggest") {
+        suggest_fixes(&args, rules);
+        return;
+    }
+
+    if args.iter().any(|a| a == "duplicates") {
+        report_duplicates(&args, rules);
+        return;
+    }
+
+    // `day4 [flags] query EXPR` only looks at the passports that match EXPR,
+    // counting them and passing them on to the reports and exports.
+    let query = args.iter().position(|a| a == "query").map(|pos| {
+        let expr = args.get(pos + 1).expect("Missing query");
+        Query::parse(expr).unwrap_or_else(|e| panic!("Invalid query, {}", e))
+    });
+
+    // `--policy NAME` picks what counts as valid (`strict` unless given), and
+    // `--all-policies` also prints how many passports each policy accepts.
+    let policies = Policy::all(rules);
+    let policy_name = flag(&args, "--policy").unwrap_or("strict");
+    let policy = policies
+        .iter()
+        .position(|p| p.name == policy_name)
+        .unwrap_or_else(|| panic!("No policy named {}", policy_name));
+    let mut accepted = vec![0u32; policies.len()];
+
+    let report_format = flag(&args, "--report");
+    // `--stats text|json` prints aggregate counts over the batch before the
+    // summary.
+    let stats_format = flag(&args, "--stats");
+    let mut stats = Stats::default();
+    // `--export FORMAT` writes the batch back out, and `--emit-valid` limits
+    // that to the valid passports (as `kv` unless a format is given). The
+    // count then goes to stderr so stdout holds only the batch.
+    let emit_valid = args.iter().any(|a| a == "--emit-valid");
+    let export_format = match flag(&args, "--export") {
+        Some(format) => Some(Format::parse(format).expect("Expected json, csv or kv")),
+        None if emit_valid => Some(Format::KeyValue),
+        None => None,
+    };
+    let stdout = io::stdout();
+    let mut exporter = export_format.map(|format| Exporter::new(stdout.lock(), format, rules));
+
+    // `--jobs N` validates on N threads (one per core unless given), and
+    // `--unordered` lets reports and exports come out in whatever order the
+    // workers finish. Counts don't depend on either.
+    let jobs = match flag(&args, "--jobs") {
+        Some(jobs) => jobs.parse().expect("Expected a number of jobs"),
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let ordered = !args.iter().any(|a| a == "--unordered");
+    // Read errors are passed along and reported on the main thread.
+    let check = |record: io::Result<Record>| -> io::Result<Verdict> {
+        let record = record?;
+        let pass = &record.passport;
+        let matches = query.as_ref().is_none_or(|q| q.matches(pass, rules));
+        let mut accepted = vec![];
+        let mut report = None;
+        if matches {
+            accepted = policies.iter().map(|p| p.accepts(pass, rules)).collect();
+            if report_format.is_some() || stats_format.is_some() {
+                report = Some(pass.validate(rules));
+            }
+        }
+        Ok(Verdict {
+            record,
+            matches,
+            accepted,
+            report,
+        })
+    };
+
     let mut valid = 0u32;
-    for line in stdin.lock().lines() {
-        let line = line.expect("Could not read line from standard in");
-        if line.len() == 0 {
-            if pass.is_valid() {
+    let mut matching = 0u32;
+    let records = PassportReader::new(BufReader::new(io::stdin()));
+    pipeline::run(
+        records,
+        jobs,
+        jobs * 64,
+        ordered,
+        check,
+        |index, verdict| {
+            let mut verdict = verdict.expect("Could not read line from standard in");
+            if !verdict.matches {
+                return;
+            }
+            matching += 1;
+            for (count, &ok) in accepted.iter_mut().zip(verdict.accepted.iter()) {
+                if ok {
+                    *count += 1;
+                }
+            }
+            let is_valid = verdict.accepted[policy];
+            if is_valid {
                 valid += 1;
             }
-            pass.reset();
-        } else {
-            pass.load_fields(&line);
+            if let Some(report) = &verdict.report {
+                if stats_format.is_some() {
+                    stats.add(&verdict.record.passport, report, rules);
+                }
+            }
+            if !rules.redactions.is_empty() {
+                verdict.record = redact::record(&verdict.record, rules);
+                verdict.report = verdict.report.map(|r| redact::report(&r, rules));
+            }
+            let pass = &verdict.record.passport;
+            if let Some(exporter) = exporter.as_mut().filter(|_| is_valid || !emit_valid) {
+                exporter
+                    .write(pass)
+                    .expect("Could not write to standard out");
+            }
+            let lines = &verdict.record.lines;
+            let (first, last) = (*lines.start(), *lines.end());
+            match (report_format, &verdict.report) {
+                (Some("json"), Some(report)) => {
+                    let members = [
+                        ("passport", (index + 1).to_string()),
+                        ("lines", json::array(&[first.to_string(), last.to_string()])),
+                        ("report", report.to_json()),
+                    ];
+                    println!("{}", json::object(&members));
+                }
+                (Some("diagnostics"), Some(report)) => {
+                    print!("{}", diagnostic::render(&verdict.record, report))
+                }
+                (Some(_), Some(report)) if !report.is_valid() || !report.warnings.is_empty() => {
+                    print!(
+                        "passport {} (lines {}-{}): {}",
+                        index + 1,
+                        first,
+                        last,
+                        report
+                    );
+                }
+                (Some(_), _) => {}
+                _ if rules.unknown_keys == UnknownKeys::Warn => {
+                    for field in pass.fields.iter().filter(|f| rules.rule(f.key()).is_none()) {
+                        eprintln!(
+                            "warning: lines {}-{}: unknown key in {}",
+                            first, last, field
+                        );
+                    }
+                }
+                _ => {}
+            }
+        },
+    );
+
+    match stats_format {
+        Some("json") => println!("{}", stats.to_json()),
+        Some(_) => print!("{}", stats),
+        None => {}
+    }
+    let mut summary = format!("Valid passports: {}", valid);
+    if query.is_some() {
+        summary = format!("Matching passports: {}\n{}", matching, summary);
+    }
+    if args.iter().any(|a| a == "--all-policies") {
+        for (p, count) in policies.iter().zip(accepted) {
+            summary.push_str(&format!("\nPolicy {}: {}", p.name, count));
         }
     }
-
-    println!("Valid passports: {}", valid);
+    if exporter.is_some() {
+        eprintln!("{}", summary);
+    } else {
+        println!("{}", summary);
+    }
 }
 
 #[cfg(test)]
@@ -206,11 +924,45 @@ mod test {
     #[test]
     fn converts_dimensions() {
         let d1: Dimension = "10cm".into();
-        assert_eq!(d1, Dimension(10, DimensionType::Cm));
+        assert_eq!(d1, Dimension(10 * MICROS, DimensionType::Cm));
         let d1: Dimension = "33in".into();
-        assert_eq!(d1, Dimension(33, DimensionType::In));
+        assert_eq!(d1, Dimension(33 * MICROS, DimensionType::In));
         let d1: Dimension = "33".into();
-        assert_eq!(d1, Dimension(33, DimensionType::Unknown));
+        assert_eq!(d1, Dimension(33 * MICROS, DimensionType::Unknown));
+        let d1: Dimension = "1.80m".into();
+        assert_eq!(d1, Dimension(1_800_000, DimensionType::M));
+        assert_eq!(d1.to_string(), "1.8m");
+        let d1: Dimension = "5'11\"".into();
+        assert_eq!(d1, Dimension(71 * MICROS, DimensionType::FtIn));
+        assert_eq!(d1.to_string(), "5'11\"");
+        assert_eq!(
+            Dimension::parse("5'"),
+            Some(Dimension(60 * MICROS, DimensionType::FtIn))
+        );
+        assert_eq!(Dimension::parse("1.2.3m"), None);
+        assert_eq!(Dimension::parse("180ftin"), None);
+        assert_eq!(Dimension::parse("tall"), None);
+    }
+
+    #[test]
+    fn converts_between_units() {
+        let height: Dimension = "5'11\"".into();
+        assert_eq!(height.micrometres(), Some(1_803_400));
+        assert_eq!(height.normalized().unwrap().to_string(), "180.34cm");
+        let height: Dimension = "1803.4mm".into();
+        assert_eq!(
+            height.convert(DimensionType::FtIn).unwrap().to_string(),
+            "5'11\""
+        );
+        assert_eq!(
+            Dimension::parse("59in")
+                .unwrap()
+                .normalized()
+                .unwrap()
+                .to_string(),
+            "149.86cm"
+        );
+        assert_eq!(Dimension::parse("180").unwrap().normalized(), None);
     }
 
     #[test]
@@ -222,7 +974,10 @@ mod test {
         let f1: Field = "eyr:2024".into();
         assert_eq!(f1, Field::ExpirationYear(Year(2024)));
         let f1: Field = "hgt:179cm".into();
-        assert_eq!(f1, Field::Height(Dimension(179, DimensionType::Cm)));
+        assert_eq!(
+            f1,
+            Field::Height(Dimension(179 * MICROS, DimensionType::Cm))
+        );
         let f1: Field = "hcl:#cfa07d".into();
         assert_eq!(f1, Field::HairColor(HexColor("#cfa07d".to_string())));
         let f1: Field = "ecl:brn".into();
@@ -232,4 +987,207 @@ mod test {
         let f1: Field = "cid:350".into();
         assert_eq!(f1, Field::CountryID(ID("350".to_string())));
     }
+
+    #[test]
+    fn reports_malformed_values() {
+        let rules = default_rules();
+        let rules = rules.document("passport").unwrap();
+        let input =
+            "byr:19x7 iyr:2015 eyr:2025 hgt:6ft\nhcl:#123abc ecl:brn pid:087499704\n\nhgt:tall";
+        let records = PassportReader::new(input.as_bytes())
+            .collect::<io::Result<Vec<Record>>>()
+            .unwrap();
+        let reasons = |record: &Record| {
+            record
+                .passport
+                .validate(rules)
+                .failures
+                .into_iter()
+                .filter(|f| f.value.is_some())
+                .map(|f| (f.key, f.reason))
+                .collect::<Vec<(String, Reason)>>()
+        };
+        assert_eq!(
+            reasons(&records[0]),
+            vec![
+                ("byr".to_string(), Reason::NotANumber),
+                ("hgt".to_string(), Reason::BadUnit)
+            ]
+        );
+        assert_eq!(
+            reasons(&records[1]),
+            vec![("hgt".to_string(), Reason::NotANumber)]
+        );
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        let color = |s: &str| HexColor(s.to_string());
+        let rgb = Rgb {
+            r: 0xcf,
+            g: 0xa0,
+            b: 0x7d,
+        };
+        assert_eq!(color("#cfa07d").parse(ColorCase::Lower, false), Ok(rgb));
+        assert_eq!(color("#CFA07D").parse(ColorCase::Any, false), Ok(rgb));
+        assert_eq!(rgb.to_string(), "#cfa07d");
+        assert_eq!(
+            color("#f0A")
+                .parse(ColorCase::Any, true)
+                .map(HexColor::from),
+            Ok(color("#ff00aa"))
+        );
+
+        let lower = |s: &str| color(s).parse(ColorCase::Lower, false);
+        assert_eq!(lower("cfa07d"), Err(ColorError::MissingHash));
+        assert_eq!(lower("#f0a"), Err(ColorError::WrongLength(3)));
+        assert_eq!(lower("#cza07d"), Err(ColorError::NotHex('z')));
+        assert_eq!(lower("#cFa07d"), Err(ColorError::WrongCase('F')));
+        assert_eq!(
+            color("#cfa07d").parse(ColorCase::Upper, false),
+            Err(ColorError::WrongCase('c'))
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_keys() {
+        let f1: Field = "foo:bar".into();
+        assert_eq!(
+            f1,
+            Field::Unknown {
+                key: "foo".to_string(),
+                value: "bar".to_string()
+            }
+        );
+        let pass = passport("byr:1937 foo:bar\ncid:350");
+        assert_eq!(pass.to_string(), "byr:1937 foo:bar cid:350");
+    }
+
+    #[test]
+    fn applies_unknown_key_policy() {
+        let mut rules = default_rules().document("passport").unwrap().clone();
+        let pass = passport(
+            "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f foo:bar",
+        );
+        let report = pass.validate(&rules);
+        assert!(pass.is_valid(&rules) && report.is_valid());
+        assert_eq!(report.warnings[0].reason, Reason::UnknownKey);
+
+        rules.unknown_keys = UnknownKeys::Allow;
+        assert!(pass.validate(&rules).warnings.is_empty());
+
+        rules.unknown_keys = UnknownKeys::Reject;
+        assert!(!pass.is_valid(&rules));
+        assert_eq!(pass.validate(&rules).failures[0].reason, Reason::UnknownKey);
+    }
+
+    #[test]
+    fn resolves_duplicate_keys() {
+        let mut rules = default_rules().document("passport").unwrap().clone();
+        let pass =
+            passport("byr:1937 byr:1980 iyr:2017 eyr:2020 hgt:183cm hcl:#fffffd ecl:gry cid:1");
+        assert_eq!(pass.fields.len(), 8);
+        assert!(!pass.is_complete(&rules));
+
+        let pass = passport(
+            "byr:1937 iyr:2017 eyr:2020 hgt:183cm hcl:#fffffd ecl:gry pid:860033327 byr:1900",
+        );
+        assert!(!pass.is_valid(&rules));
+        let report = pass.validate(&rules);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].reason, Reason::DuplicateKey);
+        assert_eq!(report.failures[0].value, Some("1900".to_string()));
+
+        rules.duplicate_keys = DuplicateKeys::FirstWins;
+        assert!(pass.is_valid(&rules));
+        assert_eq!(
+            pass.validate(&rules).warnings[0].reason,
+            Reason::DuplicateKey
+        );
+
+        rules.duplicate_keys = DuplicateKeys::LastWins;
+        assert!(!pass.is_valid(&rules));
+        let report = pass.validate(&rules);
+        assert_eq!(report.warnings[0].value, Some("1937".to_string()));
+        assert!(matches!(
+            report.failures[0].reason,
+            Reason::OutOfRange { .. }
+        ));
+    }
+
+    fn default_rules() -> RuleSet {
+        RuleSet::parse(DEFAULT_RULES).unwrap()
+    }
+
+    fn passport(s: &str) -> Passport {
+        let mut pass = Passport::new();
+        for line in s.lines() {
+            pass.load_fields(line);
+        }
+        pass
+    }
+
+    #[test]
+    fn validates_with_default_rules() {
+        let rules = default_rules();
+        let rules = rules.document("passport").unwrap();
+        let valid =
+            passport("pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980\nhcl:#623a2f");
+        assert!(valid.is_complete(rules));
+        assert!(valid.is_valid(rules));
+
+        let invalid =
+            passport("eyr:1972 cid:100\nhcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926");
+        assert!(invalid.is_complete(rules));
+        assert!(!invalid.is_valid(rules));
+        assert!(!invalid.validate(rules).is_valid());
+        let reasons = invalid
+            .validate(rules)
+            .failures
+            .into_iter()
+            .map(|f| (f.key, f.reason))
+            .collect::<Vec<(String, Reason)>>();
+        assert_eq!(
+            reasons,
+            vec![
+                (
+                    "eyr".to_string(),
+                    Reason::OutOfRange {
+                        min: 2020,
+                        max: 2030
+                    }
+                ),
+                ("hgt".to_string(), Reason::BadUnit),
+                ("pid".to_string(), Reason::WrongLength { expected: 9 }),
+            ]
+        );
+
+        // Checks still apply once both sides pass on their own.
+        let mut expiring = rules.clone();
+        expiring.reference_year = Some(2025);
+        let expired =
+            passport("pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2021 byr:1980\nhcl:#623a2f");
+        let reasons = expired
+            .validate(&expiring)
+            .failures
+            .into_iter()
+            .map(|f| (f.key, f.reason))
+            .collect::<Vec<(String, Reason)>>();
+        assert_eq!(
+            reasons,
+            vec![(
+                "eyr".to_string(),
+                Reason::Inconsistent {
+                    check: "eyr >= reference".to_string()
+                }
+            )]
+        );
+
+        let incomplete = passport("hcl:#cfa07d eyr:2025 pid:166559648\niyr:2011 ecl:brn hgt:59in");
+        assert!(!incomplete.is_complete(rules));
+        let report = incomplete.validate(rules);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].key, "byr");
+        assert_eq!(report.failures[0].reason, Reason::Missing);
+    }
 }
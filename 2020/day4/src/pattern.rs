@@ -0,0 +1,200 @@
+use std::fmt;
+
+// A small regex-like matcher for rule files. Patterns always match the whole
+// value and support literals, `\` escapes, `.`, character classes such as
+// `[0-9a-f]` or `[^#]`, and the quantifiers `?`, `*`, `+`, `{n}`, `{n,}` and
+// `{n,m}`. There is no alternation or grouping.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    source: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Token {
+    atom: Atom,
+    min: usize,
+    max: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Atom {
+    Any,
+    Char(char),
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Any => true,
+            Atom::Char(expected) => c == *expected,
+            Atom::Class { negated, ranges } => {
+                ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }
+        }
+    }
+}
+
+impl Pattern {
+    pub fn parse(s: &str) -> Result<Pattern, String> {
+        let mut chars = s.chars().peekable();
+        let mut tokens: Vec<Token> = vec![];
+        // Whether the last token already has a quantifier; `a*?` would
+        // otherwise quietly become `a?`.
+        let mut quantified = false;
+        while let Some(c) = chars.next() {
+            let atom = match c {
+                '.' => Atom::Any,
+                '\\' => Atom::Char(chars.next().ok_or("dangling escape")?),
+                '[' => {
+                    let negated = chars.peek() == Some(&'^');
+                    if negated {
+                        chars.next();
+                    }
+                    let mut ranges = vec![];
+                    loop {
+                        let lo = match chars.next() {
+                            Some(']') => break,
+                            Some('\\') => chars.next().ok_or("dangling escape")?,
+                            Some(c) => c,
+                            None => return Err("unterminated character class".to_string()),
+                        };
+                        let hi = if chars.peek() == Some(&'-') {
+                            chars.next();
+                            match chars.next() {
+                                Some(']') | None => return Err("unterminated range".to_string()),
+                                Some(c) => c,
+                            }
+                        } else {
+                            lo
+                        };
+                        ranges.push((lo, hi));
+                    }
+                    Atom::Class { negated, ranges }
+                }
+                '?' | '*' | '+' | '{' => {
+                    let last = tokens
+                        .last_mut()
+                        .ok_or("quantifier without anything to repeat")?;
+                    if quantified {
+                        return Err(format!("{} follows another quantifier", c));
+                    }
+                    let (min, max) = match c {
+                        '?' => (0, 1),
+                        '*' => (0, usize::MAX),
+                        '+' => (1, usize::MAX),
+                        _ => parse_repetition(&mut chars)?,
+                    };
+                    last.min = min;
+                    last.max = max;
+                    quantified = true;
+                    continue;
+                }
+                c => Atom::Char(c),
+            };
+            tokens.push(Token {
+                atom,
+                min: 1,
+                max: 1,
+            });
+            quantified = false;
+        }
+
+        Ok(Pattern {
+            source: s.to_string(),
+            tokens,
+        })
+    }
+
+    pub fn matches(&self, s: &str) -> bool {
+        let input = s.chars().collect::<Vec<char>>();
+        match_tokens(&self.tokens, &input)
+    }
+}
+
+fn parse_repetition(chars: &mut impl Iterator<Item = char>) -> Result<(usize, usize), String> {
+    let mut body = String::new();
+    for c in chars {
+        if c == '}' {
+            let number = |s: &str| {
+                s.trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid repetition {{{}}}", body))
+            };
+            return match body.split_once(',') {
+                None => number(&body).map(|n| (n, n)),
+                Some((min, "")) => Ok((number(min)?, usize::MAX)),
+                Some((min, max)) => match (number(min)?, number(max)?) {
+                    (min, max) if min > max => Err(format!("invalid repetition {{{}}}", body)),
+                    bounds => Ok(bounds),
+                },
+            };
+        }
+        body.push(c);
+    }
+
+    Err("unterminated repetition".to_string())
+}
+
+// Greedy with backtracking, which is plenty for the short values in passports.
+fn match_tokens(tokens: &[Token], input: &[char]) -> bool {
+    match tokens.split_first() {
+        None => input.is_empty(),
+        Some((token, rest)) => {
+            let mut n = 0;
+            while n < token.max && n < input.len() && token.atom.matches(input[n]) {
+                n += 1;
+            }
+            n >= token.min
+                && (token.min..=n)
+                    .rev()
+                    .any(|k| match_tokens(rest, &input[k..]))
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_whole_values() {
+        let hex = Pattern::parse("#[0-9a-f]{6}").unwrap();
+        assert!(hex.matches("#123abc"));
+        assert!(!hex.matches("#123abz"));
+        assert!(!hex.matches("#123abcd"));
+        assert!(!hex.matches("123abc"));
+
+        let pid = Pattern::parse("[0-9]{9}").unwrap();
+        assert!(pid.matches("000000001"));
+        assert!(!pid.matches("0123456789"));
+
+        let p = Pattern::parse("a.?b+[^x]*\\.{1,2}").unwrap();
+        assert!(p.matches("abbyz."));
+        assert!(p.matches("aXb.."));
+        assert!(!p.matches("abx."));
+        assert!(!p.matches("ab.x"));
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        assert!(Pattern::parse("[0-9").is_err());
+        assert!(Pattern::parse("*a").is_err());
+        assert!(Pattern::parse("a{2").is_err());
+        assert!(Pattern::parse("a{x}").is_err());
+        assert!(Pattern::parse("a*?").is_err());
+        assert!(Pattern::parse("a{2}+").is_err());
+        assert!(Pattern::parse("a{3,1}").is_err());
+        assert!(Pattern::parse("a{1,1}b*").is_ok());
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+
+// Runs `work` over `items` on `workers` threads and hands each result to
+// `sink` on the calling thread, along with the item's position in the input.
+// At most `window` items are read but not yet sunk, so memory stays bounded
+// however long the input is, and with `ordered` set results reach `sink` in
+// input order. Anything the sink accumulates is therefore the same whatever
+// the number of workers. A panic in `work` is passed on to the calling
+// thread, which stops the run as a panic in `sink` would.
+pub fn run<T, R, W, S>(
+    items: impl Iterator<Item = T> + Send,
+    workers: usize,
+    window: usize,
+    ordered: bool,
+    work: W,
+    mut sink: S,
+) where
+    T: Send,
+    R: Send,
+    W: Fn(T) -> R + Sync,
+    S: FnMut(usize, R),
+{
+    let workers = workers.max(1);
+    let window = window.max(workers);
+    let (job_tx, job_rx) = mpsc::sync_channel::<(usize, T)>(window);
+    let (result_tx, result_rx) = mpsc::sync_channel::<(usize, thread::Result<R>)>(window);
+    // The reader takes a credit for every item it sends and the sink gives
+    // one back for every result it consumes.
+    let (credit_tx, credit_rx) = mpsc::sync_channel::<()>(window);
+    for _ in 0..window {
+        credit_tx.send(()).unwrap();
+    }
+    let job_rx = Mutex::new(job_rx);
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for (index, item) in items.enumerate() {
+                if credit_rx.recv().is_err() || job_tx.send((index, item)).is_err() {
+                    break;
+                }
+            }
+        });
+        for _ in 0..workers {
+            let result_tx = result_tx.clone();
+            let (job_rx, work) = (&job_rx, &work);
+            scope.spawn(move || loop {
+                let job = job_rx.lock().unwrap().recv();
+                let (index, item) = match job {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| work(item)));
+                if result_tx.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_tx);
+
+        // The sink owns the credits, so if it panics they go with it and the
+        // reader and workers wind down instead of waiting forever.
+        drain(result_rx, ordered, move |index, result| {
+            sink(index, result);
+            // The reader may already be done and gone.
+            let _ = credit_tx.try_send(());
+        });
+    });
+}
+
+fn drain<R>(
+    results: Receiver<(usize, thread::Result<R>)>,
+    ordered: bool,
+    mut sink: impl FnMut(usize, R),
+) {
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    for (index, result) in results {
+        // A result lost with its worker would otherwise leave a gap the
+        // ordered sink waits on forever.
+        let result = result.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        if !ordered {
+            sink(index, result);
+            continue;
+        }
+        pending.insert(index, result);
+        while let Some(result) = pending.remove(&next) {
+            sink(next, result);
+            next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_order_and_counts() {
+        for &workers in [1, 4].iter() {
+            let mut seen = vec![];
+            run(
+                0..1000u64,
+                workers,
+                8,
+                true,
+                |n| n * n,
+                |i, r| seen.push((i, r)),
+            );
+            let expected = (0..1000u64)
+                .map(|n| (n as usize, n * n))
+                .collect::<Vec<_>>();
+            assert_eq!(seen, expected);
+
+            let mut seen = vec![];
+            run(
+                0..1000u64,
+                workers,
+                8,
+                false,
+                |n| n + 1,
+                |_, r| seen.push(r),
+            );
+            seen.sort_unstable();
+            assert_eq!(seen, (1..1001).collect::<Vec<u64>>());
+        }
+    }
+
+    #[test]
+    fn stops_when_work_panics() {
+        for &ordered in [true, false].iter() {
+            let mut sunk = 0;
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                run(
+                    0..1000u64,
+                    4,
+                    8,
+                    ordered,
+                    |n| {
+                        if n == 100 {
+                            panic!("bad record");
+                        }
+                        n
+                    },
+                    |_, _| sunk += 1,
+                )
+            }));
+            assert!(outcome.is_err());
+            assert!(sunk < 1000);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::query::Query;
+use crate::rules::DocumentRules;
+use crate::Passport;
+
+// What it takes for a passport to count. `presence` only needs the required
+// keys (part 1), `strict` also checks every value against the rules (part 2),
+// and rule files can add their own as queries:
+//
+//   @policy lenient = exists byr and exists pid
+pub struct Policy {
+    pub name: String,
+    check: Check,
+}
+
+enum Check {
+    Presence,
+    Strict,
+    Query(Query),
+}
+
+pub const BUILT_IN: [&str; 2] = ["presence", "strict"];
+
+impl Policy {
+    // The built-in policies followed by the document's own, in the order
+    // they were defined.
+    pub fn all(rules: &DocumentRules) -> Vec<Policy> {
+        let built_in = BUILT_IN
+            .iter()
+            .copied()
+            .zip(vec![Check::Presence, Check::Strict]);
+        let custom = rules
+            .policies
+            .iter()
+            .map(|(name, query)| (name.as_str(), Check::Query(query.clone())));
+        built_in
+            .chain(custom)
+            .map(|(name, check)| Policy {
+                name: name.to_string(),
+                check,
+            })
+            .collect()
+    }
+
+    pub fn accepts(&self, passport: &Passport, rules: &DocumentRules) -> bool {
+        match &self.check {
+            Check::Presence => passport.is_complete(rules),
+            Check::Strict => passport.is_valid(rules),
+            Check::Query(query) => query.matches(passport, rules),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rules::RuleSet;
+    use crate::DEFAULT_RULES;
+
+    #[test]
+    fn evaluates_every_policy() {
+        let source = DEFAULT_RULES.replacen(
+            "[passport]\n",
+            "[passport]\n@policy has-pid = exists pid\n",
+            1,
+        );
+        let rules = RuleSet::parse(&source).unwrap();
+        let rules = rules.document("passport").unwrap();
+        let policies = Policy::all(rules);
+        assert_eq!(
+            policies
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["presence", "strict", "has-pid"]
+        );
+
+        let mut pass = Passport::new();
+        pass.load_fields("byr:1937 iyr:2017 eyr:2020 hgt:183in hcl:#fffffd ecl:gry pid:860033327");
+        let verdicts = policies
+            .iter()
+            .map(|p| p.accepts(&pass, rules))
+            .collect::<Vec<bool>>();
+        assert_eq!(verdicts, vec![true, false, true]);
+    }
+}
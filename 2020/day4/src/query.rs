@@ -0,0 +1,267 @@
+use std::cmp::Ordering;
+
+use crate::pattern::Pattern;
+use crate::rules::DocumentRules;
+use crate::{Dimension, Passport};
+
+// Filters for passport batches, such as
+//
+//   valid and ecl = brn and byr < 1950
+//   exists cid or (hgt >= 6' and not hgt ~ .*cm)
+//
+// Comparisons are `=` (or `:`, so `ecl:brn` works), `!=`, `<`, `<=`, `>`
+// and `>=`. When the right-hand side is a length such as `70in` or `1.8m` the
+// value is compared as a length in any unit, when it is a whole number it is
+// compared as a number, and otherwise as text. A value of the wrong kind, or
+// a missing key, never matches. `~` matches the value against a pattern (see
+// `src/pattern.rs`), `exists KEY` checks a key is present, `valid` checks the
+// passport against the rules, and `not`, `and` and `or` combine them, from
+// tightest to loosest binding.
+#[derive(Clone)]
+pub struct Query(Expr);
+
+#[derive(Clone)]
+enum Expr {
+    Valid,
+    Exists(String),
+    Compare { key: String, op: Op, value: String },
+    Matches { key: String, pattern: Pattern },
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' | ':' => Token::Op(Op::Eq),
+            '~' => Token::Op(Op::Like),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()=:~<>!".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if self.peek() == Some(&Token::Word(word.to_string())) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn word(&mut self, what: &str) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word),
+            _ => Err(format!("expected {}", what)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat_word("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.eat_word("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat_word("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat_word("valid") {
+            return Ok(Expr::Valid);
+        }
+        if self.eat_word("exists") {
+            return Ok(Expr::Exists(self.word("a key after exists")?));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let expr = self.or()?;
+            if self.next() != Some(Token::Close) {
+                return Err("expected )".to_string());
+            }
+            return Ok(expr);
+        }
+
+        let key = self.word("a key")?;
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            _ => return Err(format!("expected a comparison after {}", key)),
+        };
+        let value = self.word(&format!("a value after {}", key))?;
+        if op == Op::Like {
+            let pattern = Pattern::parse(&value)?;
+            return Ok(Expr::Matches { key, pattern });
+        }
+        Ok(Expr::Compare { key, op, value })
+    }
+}
+
+impl Query {
+    pub fn parse(s: &str) -> Result<Query, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s),
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Query(expr)),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    pub fn matches(&self, passport: &Passport, rules: &DocumentRules) -> bool {
+        self.0.eval(passport, rules)
+    }
+}
+
+impl Expr {
+    fn eval(&self, passport: &Passport, rules: &DocumentRules) -> bool {
+        // Repeated keys resolve the same way validation does.
+        let lookup = |key: &str| passport.get(key, rules).map(|f| f.value());
+
+        match self {
+            Expr::Valid => passport.is_valid(rules),
+            Expr::Exists(key) => lookup(key).is_some(),
+            Expr::Compare { key, op, value } => {
+                let ordering = lookup(key).and_then(|field| compare(&field, value));
+                match ordering {
+                    Some(ordering) => match op {
+                        Op::Eq => ordering == Ordering::Equal,
+                        Op::Ne => ordering != Ordering::Equal,
+                        Op::Lt => ordering == Ordering::Less,
+                        Op::Le => ordering != Ordering::Greater,
+                        Op::Gt => ordering == Ordering::Greater,
+                        Op::Ge => ordering != Ordering::Less,
+                        Op::Like => unreachable!(),
+                    },
+                    None => false,
+                }
+            }
+            Expr::Matches { key, pattern } => lookup(key).is_some_and(|v| pattern.matches(&v)),
+            Expr::Not(expr) => !expr.eval(passport, rules),
+            Expr::And(lhs, rhs) => lhs.eval(passport, rules) && rhs.eval(passport, rules),
+            Expr::Or(lhs, rhs) => lhs.eval(passport, rules) || rhs.eval(passport, rules),
+        }
+    }
+}
+
+// How `field` compares to the query's `value`, if they are the same kind of
+// value.
+fn compare(field: &str, value: &str) -> Option<Ordering> {
+    let length = |s: &str| Dimension::parse(s).and_then(|d| d.micrometres());
+    if let Some(value) = length(value) {
+        return length(field).map(|field| field.cmp(&value));
+    }
+    if let Ok(value) = value.parse::<i64>() {
+        return field.parse::<i64>().ok().map(|field| field.cmp(&value));
+    }
+    Some(field.cmp(value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rules::RuleSet;
+    use crate::DEFAULT_RULES;
+
+    fn matches(query: &str, fields: &str) -> bool {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let mut pass = Passport::new();
+        pass.load_fields(fields);
+        Query::parse(query)
+            .unwrap()
+            .matches(&pass, rules.document("passport").unwrap())
+    }
+
+    #[test]
+    fn evaluates_queries() {
+        let pass = "ecl:brn byr:1937 hgt:183cm hcl:#fffffd";
+        assert!(matches("ecl:brn and byr<1950", pass));
+        assert!(!matches("ecl = brn and byr >= 1950", pass));
+        assert!(matches("hgt > 5'11\"", pass));
+        assert!(matches("hgt <= 72.1in and hgt != 1.8m", pass));
+        assert!(!matches("hgt ~ .*in", pass));
+        assert!(matches(
+            "not exists pid and (exists cid or hcl ~ #f+d)",
+            pass
+        ));
+        assert!(!matches("byr < 1950in or ecl > 5", pass));
+        assert!(!matches("valid", pass));
+        assert!(matches("byr:1980", "byr:1980 byr:1937"));
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        assert!(Query::parse("byr <").is_err());
+        assert!(Query::parse("(byr < 1950").is_err());
+        assert!(Query::parse("byr 1950").is_err());
+        assert!(Query::parse("valid valid").is_err());
+        assert!(Query::parse("hcl ~ [a-f").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{self, BufRead};
+use std::ops::{Range, RangeInclusive};
+
+use crate::Passport;
+
+pub struct Record {
+    pub passport: Passport,
+    // 1-based lines the record spans in the input.
+    pub lines: RangeInclusive<usize>,
+    // Where each of `passport.fields` was, index for index.
+    pub spans: Vec<Span>,
+    // The text of each line in `lines`, without its line ending.
+    pub source: Vec<String>,
+}
+
+// Where a `key:value` token sits in the input: its 1-based line and column,
+// counted in characters, and its byte offsets from the start of the input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub bytes: Range<usize>,
+}
+
+// Yields one record per blank-line-separated batch entry. Lines holding only
+// whitespace count as blank, `\r\n` endings are accepted, and the last record
+// doesn't need a trailing blank line.
+pub struct PassportReader<R> {
+    input: R,
+    line_no: usize,
+    // Bytes read so far.
+    offset: usize,
+}
+
+impl<R: BufRead> PassportReader<R> {
+    pub fn new(input: R) -> Self {
+        PassportReader {
+            input,
+            line_no: 0,
+            offset: 0,
+        }
+    }
+}
+
+// The whitespace-separated tokens of `line` with their byte offsets, split
+// the same way as `Passport::load_fields`.
+pub fn tokens(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (i, c) in line.char_indices().chain(Some((line.len(), ' '))) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                tokens.push((s, &line[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+impl<R: BufRead> Iterator for PassportReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut passport = Passport::new();
+        let mut spans = vec![];
+        let mut source = vec![];
+        let mut first = None;
+        let mut last = 0;
+        loop {
+            let mut line = String::new();
+            let start = self.offset;
+            match self.input.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(n) => self.offset += n,
+                Err(e) => return Some(Err(e)),
+            }
+            self.line_no += 1;
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.trim().is_empty() {
+                if first.is_some() {
+                    break;
+                }
+                continue;
+            }
+            first.get_or_insert(self.line_no);
+            last = self.line_no;
+            passport.load_fields(line);
+            spans.extend(tokens(line).into_iter().map(|(at, token)| Span {
+                line: self.line_no,
+                column: line[..at].chars().count() + 1,
+                bytes: start + at..start + at + token.len(),
+            }));
+            source.push(line.to_string());
+        }
+
+        first.map(|first| {
+            Ok(Record {
+                passport,
+                lines: first..=last,
+                spans,
+                source,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_records_with_line_ranges() {
+        let input =
+            "\n byr:1937\tiyr:2017\r\ncid:147  hgt:183cm\r\n \t\n\n\necl:amb\npid:028048884";
+        let records = PassportReader::new(input.as_bytes())
+            .collect::<io::Result<Vec<Record>>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].passport.fields.len(), 4);
+        assert_eq!(records[0].lines, 2..=3);
+        assert_eq!(records[1].passport.fields.len(), 2);
+        assert_eq!(records[1].lines, 7..=8);
+        assert_eq!(
+            records[0].spans[1],
+            Span {
+                line: 2,
+                column: 11,
+                bytes: 11..19
+            }
+        );
+        assert_eq!(&input[records[0].spans[3].bytes.clone()], "hgt:183cm");
+        assert_eq!(records[0].spans[3].column, 10);
+        assert_eq!(
+            records[0].source,
+            vec![" byr:1937\tiyr:2017", "cid:147  hgt:183cm"]
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use crate::dedup::Duplicate;
+use crate::reader::{self, Record, Span};
+use crate::report::{Failure, ValidationReport};
+use crate::rules::DocumentRules;
+use crate::suggest::Suggestion;
+use crate::{Field, Passport};
+
+// How the values of a key are shown when output is redacted, set per key in
+// the rule file:
+//
+//   @redact pid = mask
+//   @redact cid = mask keep=2
+//   @redact byr = bucket 10
+//
+// Keys without a `@redact` line are shown as they are. Redaction only
+// changes what gets written out; validation, counts and statistics always
+// see the real values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Redaction {
+    // Every character but the last `keep` becomes `*`, so the length of the
+    // value still shows.
+    Mask { keep: usize },
+    // Numbers become the range of the given width they fall in, `1930-1939`
+    // for `1937` with `bucket 10`. Anything else is masked.
+    Bucket(u64),
+}
+
+impl Redaction {
+    pub fn parse(s: &str) -> Result<Redaction, String> {
+        let words = s.split_whitespace().collect::<Vec<&str>>();
+        match words[..] {
+            ["mask"] => Ok(Redaction::Mask { keep: 0 }),
+            ["mask", keep] => {
+                let keep = keep
+                    .strip_prefix("keep=")
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| format!("expected keep=N, got {}", keep))?;
+                Ok(Redaction::Mask { keep })
+            }
+            ["bucket", width] => match width.parse() {
+                Ok(width) if width > 0 => Ok(Redaction::Bucket(width)),
+                _ => Err(format!("invalid bucket width {}", width)),
+            },
+            _ => Err(format!("expected mask [keep=N] or bucket N, got {}", s)),
+        }
+    }
+
+    pub fn apply(&self, value: &str) -> String {
+        match (self, value.parse::<u64>()) {
+            (Redaction::Bucket(width), Ok(n)) => {
+                let first = n / width * width;
+                format!("{}-{}", first, first + width - 1)
+            }
+            (Redaction::Bucket(_), Err(_)) => Redaction::Mask { keep: 0 }.apply(value),
+            (Redaction::Mask { keep }, _) => {
+                let hidden = value.chars().count().saturating_sub(*keep);
+                value
+                    .chars()
+                    .enumerate()
+                    .map(|(i, c)| if i < hidden { '*' } else { c })
+                    .collect()
+            }
+        }
+    }
+}
+
+// `value` as shown for `key`.
+pub fn value(rules: &DocumentRules, key: &str, value: &str) -> String {
+    match rules.redactions.iter().find(|(k, _)| k == key) {
+        Some((_, redaction)) => redaction.apply(value),
+        None => value.to_string(),
+    }
+}
+
+// A redacted value no longer parses as its key's type, a bucketed year for
+// one, so redacted fields are kept as plain text under the same key.
+fn field(rules: &DocumentRules, field: &Field) -> Field {
+    if rules.redactions.iter().any(|(k, _)| k == field.key()) {
+        Field::Unknown {
+            key: field.key().to_string(),
+            value: value(rules, field.key(), &field.value()),
+        }
+    } else {
+        field.clone()
+    }
+}
+
+pub fn passport(passport: &Passport, rules: &DocumentRules) -> Passport {
+    Passport {
+        fields: passport.fields.iter().map(|f| field(rules, f)).collect(),
+    }
+}
+
+pub fn report(report: &ValidationReport, rules: &DocumentRules) -> ValidationReport {
+    let failures = |failures: &[Failure]| {
+        failures
+            .iter()
+            .map(|f| Failure {
+                value: f.value.as_ref().map(|v| value(rules, &f.key, v)),
+                ..f.clone()
+            })
+            .collect()
+    };
+    ValidationReport {
+        failures: failures(&report.failures),
+        warnings: failures(&report.warnings),
+    }
+}
+
+// Redacts the source lines too, moving the columns of the spans to match.
+// Byte offsets still point into the original input.
+pub fn record(record: &Record, rules: &DocumentRules) -> Record {
+    // Fields and spans line up with the tokens, in order.
+    let mut fields = record.passport.fields.iter().zip(record.spans.iter());
+    let mut source = vec![];
+    let mut spans = vec![];
+    for line in record.source.iter() {
+        let mut shown = String::new();
+        let mut end = 0;
+        for (at, token) in reader::tokens(line) {
+            shown.push_str(&line[end..at]);
+            let (field, span) = fields.next().expect("A field for every token");
+            spans.push(Span {
+                column: shown.chars().count() + 1,
+                ..span.clone()
+            });
+            if rules.redactions.iter().any(|(k, _)| k == field.key()) {
+                shown.push_str(&self::field(rules, field).to_string());
+            } else {
+                shown.push_str(token);
+            }
+            end = at + token.len();
+        }
+        shown.push_str(&line[end..]);
+        source.push(shown);
+    }
+
+    Record {
+        passport: passport(&record.passport, rules),
+        lines: record.lines.clone(),
+        spans,
+        source,
+    }
+}
+
+pub fn duplicate(duplicate: &Duplicate, rules: &DocumentRules) -> Duplicate {
+    Duplicate {
+        pid: value(rules, "pid", &duplicate.pid),
+        cid: duplicate.cid.as_ref().map(|cid| value(rules, "cid", cid)),
+        records: duplicate.records.clone(),
+        conflicts: duplicate.conflicts.clone(),
+    }
+}
+
+pub fn suggestion(suggestion: &Suggestion, rules: &DocumentRules) -> Suggestion {
+    let key = &suggestion.key;
+    Suggestion {
+        field: suggestion.field,
+        key: key.clone(),
+        value: value(rules, key, &suggestion.value),
+        candidates: suggestion
+            .candidates
+            .iter()
+            .map(|c| value(rules, key, c))
+            .collect(),
+        confidence: suggestion.confidence,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reader::PassportReader;
+    use crate::rules::RuleSet;
+    use crate::DEFAULT_RULES;
+
+    #[test]
+    fn hides_ids_and_birth_years() {
+        assert_eq!(Redaction::Mask { keep: 0 }.apply("087499704"), "*********");
+        assert_eq!(Redaction::Mask { keep: 3 }.apply("087499704"), "******704");
+        assert_eq!(Redaction::Mask { keep: 9 }.apply("12"), "12");
+        assert_eq!(Redaction::Bucket(10).apply("1937"), "1930-1939");
+        assert_eq!(Redaction::Bucket(25).apply("1937"), "1925-1949");
+        assert_eq!(Redaction::Bucket(10).apply("19x7"), "****");
+        assert!(Redaction::parse("mask keep=x").is_err());
+
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let rules = rules.document("passport").unwrap();
+        let input = "iyr:2015 eyr:2025 byr:1900\n  pid:087499704 cid:147 hgt:170cm";
+        let original = PassportReader::new(input.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        let record = record(&original, rules);
+        assert_eq!(
+            record.source,
+            vec![
+                "iyr:2015 eyr:2025 byr:1900-1909",
+                "  pid:********* cid:*** hgt:170cm"
+            ]
+        );
+        assert_eq!(
+            record.passport.to_string(),
+            "iyr:2015 eyr:2025 byr:1900-1909 pid:********* cid:*** hgt:170cm"
+        );
+        assert_eq!(record.spans[4].column, 17);
+        assert_eq!(record.spans[4].bytes, original.spans[4].bytes);
+
+        let report = report(&original.passport.validate(rules), rules);
+        assert!(report
+            .to_string()
+            .contains("byr:1900-1909 not between 1920 and 2002"));
+        assert!(!report.to_json().contains("1900\""));
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+
+use crate::json;
+use crate::{ColorError, Field};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reason {
+    Missing,
+    NotANumber,
+    OutOfRange { min: u32, max: u32 },
+    // Bounds of a length range, already normalized to a single unit.
+    LengthOutOfRange { min: String, max: String },
+    BadUnit,
+    MalformedColor { error: ColorError },
+    WrongLength { expected: usize },
+    PatternMismatch { pattern: String },
+    NotAllowed { values: Vec<String> },
+    UnknownKey,
+    DuplicateKey,
+    // A record-level check across several keys, such as `iyr >= byr + 16`.
+    Inconsistent { check: String },
+    // Rejected by a validator registered for the key.
+    Invalid { validator: String, detail: String },
+}
+
+impl Reason {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Reason::Missing => "missing",
+            Reason::NotANumber => "not_a_number",
+            Reason::OutOfRange { .. } | Reason::LengthOutOfRange { .. } => "out_of_range",
+            Reason::BadUnit => "bad_unit",
+            Reason::MalformedColor { .. } => "malformed_color",
+            Reason::WrongLength { .. } => "wrong_length",
+            Reason::PatternMismatch { .. } => "pattern_mismatch",
+            Reason::NotAllowed { .. } => "not_allowed",
+            Reason::UnknownKey => "unknown_key",
+            Reason::DuplicateKey => "duplicate_key",
+            Reason::Inconsistent { .. } => "inconsistent",
+            Reason::Invalid { .. } => "invalid",
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let mut members = vec![("code", json::string(self.code()))];
+        match self {
+            Reason::OutOfRange { min, max } => {
+                members.push(("min", min.to_string()));
+                members.push(("max", max.to_string()));
+            }
+            Reason::LengthOutOfRange { min, max } => {
+                members.push(("min", json::string(min)));
+                members.push(("max", json::string(max)));
+            }
+            Reason::MalformedColor { error } => {
+                members.push(("error", json::string(&error.to_string())))
+            }
+            Reason::WrongLength { expected } => members.push(("expected", expected.to_string())),
+            Reason::PatternMismatch { pattern } => members.push(("pattern", json::string(pattern))),
+            Reason::Inconsistent { check } => members.push(("check", json::string(check))),
+            Reason::Invalid { validator, detail } => {
+                members.push(("validator", json::string(validator)));
+                members.push(("detail", json::string(detail)));
+            }
+            Reason::NotAllowed { values } => {
+                let values = values
+                    .iter()
+                    .map(|v| json::string(v))
+                    .collect::<Vec<String>>();
+                members.push(("values", json::array(&values)));
+            }
+            _ => {}
+        }
+        json::object(&members)
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Missing => write!(f, "required key is missing"),
+            Reason::NotANumber => write!(f, "not a number"),
+            Reason::OutOfRange { min, max } => write!(f, "not between {} and {}", min, max),
+            Reason::LengthOutOfRange { min, max } => {
+                write!(f, "not between {} and {}", min, max)
+            }
+            Reason::BadUnit => write!(f, "missing or unsupported unit"),
+            Reason::MalformedColor { error } => write!(f, "malformed color, {}", error),
+            Reason::WrongLength { expected } => write!(f, "expected {} characters", expected),
+            Reason::PatternMismatch { pattern } => write!(f, "does not match {}", pattern),
+            Reason::NotAllowed { values } => write!(f, "not one of {}", values.join(", ")),
+            Reason::UnknownKey => write!(f, "unknown key"),
+            Reason::DuplicateKey => write!(f, "key given more than once"),
+            Reason::Inconsistent { check } => write!(f, "fails {}", check),
+            Reason::Invalid { validator, detail } => write!(f, "{} ({})", detail, validator),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    pub key: String,
+    // `None` when the key is missing altogether.
+    pub value: Option<String>,
+    pub reason: Reason,
+    // Index into the passport's fields when the failure is about one of them.
+    pub field: Option<usize>,
+}
+
+impl Failure {
+    pub fn to_json(&self) -> String {
+        let value = match &self.value {
+            Some(value) => json::string(value),
+            None => "null".to_string(),
+        };
+        json::object(&[
+            ("key", json::string(&self.key)),
+            ("value", value),
+            ("reason", self.reason.to_json()),
+        ])
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}:{} {}", self.key, value, self.reason),
+            None => write!(f, "{} {}", self.key, self.reason),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    pub failures: Vec<Failure>,
+    // Problems worth pointing out that don't reject the record.
+    pub warnings: Vec<Failure>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    pub fn push(&mut self, key: &str, value: Option<&str>, reason: Reason) {
+        self.failures.push(Failure {
+            key: key.to_string(),
+            value: value.map(String::from),
+            reason,
+            field: None,
+        });
+    }
+
+    pub fn warn(&mut self, key: &str, value: Option<&str>, reason: Reason) {
+        self.warnings.push(Failure {
+            key: key.to_string(),
+            value: value.map(String::from),
+            reason,
+            field: None,
+        });
+    }
+
+    // Like `push`, for the field at `index` in the passport.
+    pub fn push_field(&mut self, index: usize, field: &Field, reason: Reason) {
+        self.push(field.key(), Some(&field.value()), reason);
+        self.failures.last_mut().unwrap().field = Some(index);
+    }
+
+    pub fn warn_field(&mut self, index: usize, field: &Field, reason: Reason) {
+        self.warn(field.key(), Some(&field.value()), reason);
+        self.warnings.last_mut().unwrap().field = Some(index);
+    }
+
+    pub fn to_json(&self) -> String {
+        let to_json = |failures: &[Failure]| {
+            json::array(
+                &failures
+                    .iter()
+                    .map(|f| f.to_json())
+                    .collect::<Vec<String>>(),
+            )
+        };
+        json::object(&[
+            ("valid", self.is_valid().to_string()),
+            ("failures", to_json(&self.failures)),
+            ("warnings", to_json(&self.warnings)),
+        ])
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", if self.is_valid() { "valid" } else { "rejected" })?;
+        for failure in self.failures.iter() {
+            writeln!(f, "  {}", failure)?;
+        }
+        for warning in self.warnings.iter() {
+            writeln!(f, "  warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders_text_and_json() {
+        let mut report = ValidationReport::default();
+        assert_eq!(report.to_string(), "valid\n");
+        report.push("pid", None, Reason::Missing);
+        report.push(
+            "byr",
+            Some("1900"),
+            Reason::OutOfRange {
+                min: 1920,
+                max: 2002,
+            },
+        );
+        assert_eq!(
+            report.to_string(),
+            "rejected\n  pid required key is missing\n  byr:1900 not between 1920 and 2002\n"
+        );
+        report.warn("foo", Some("bar"), Reason::UnknownKey);
+        assert_eq!(
+            report.to_json(),
+            "{\"valid\":false,\"failures\":[\
+             {\"key\":\"pid\",\"value\":null,\"reason\":{\"code\":\"missing\"}},\
+             {\"key\":\"byr\",\"value\":\"1900\",\"reason\":{\"code\":\"out_of_range\",\"min\":1920,\"max\":2002}}],\
+             \"warnings\":[{\"key\":\"foo\",\"value\":\"bar\",\"reason\":{\"code\":\"unknown_key\"}}]}"
+        );
+    }
+}
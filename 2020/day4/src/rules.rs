@@ -0,0 +1,965 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::pattern::Pattern;
+use crate::policy;
+use crate::query::Query;
+use crate::redact::Redaction;
+use crate::report::Reason;
+use crate::validator::{self, FieldValidator};
+use crate::{ColorCase, ColorError, Dimension, DimensionType, HexColor, MICROS};
+
+// Validation rules loaded from a rule file such as `res/passport.rules`.
+// Each `[section]` describes one document type and each line in it one key:
+//
+//   <key> = <type> [required] [constraint...]
+//
+// Types are `number`, `height`, `hexcolor` and `text`. Constraints are
+// `range=LO..HI` for numbers and heights, where height bounds carry a unit
+// (`range=59in..76in`) and are compared after converting to micrometres,
+// `units=cm|in` to restrict the units a height may be written in,
+// `case=lower|upper|any` and `short=allow|reject` for the letter case and the
+// `#rgb` form of hex colors (any case and both forms by default), and
+// `len=N`, `pattern=...` and `values=a|b|c` for the raw text of any type. Lines
+// starting with `#` are comments.
+//
+// Lines starting with `@` are document settings rather than keys:
+//
+//   @unknown-keys = allow | warn | reject
+//   @duplicate-keys = first | last | reject
+//   @policy <name> = <query>
+//   @reference-year = 2020
+//   @check <key> <op> <key | number | reference> [+ N | - N]
+//   @validate <key> = <validator> [argument]
+//   @redact <key> = mask [keep=N] | bucket N
+//
+// decide what happens to keys that have no rule (defaults to `warn`) and to
+// keys given more than once (defaults to `reject`), add named policies next
+// to the built-in `presence` and `strict` (see `src/policy.rs`), and add
+// checks across the fields of a record, such as `@check iyr >= byr + 16` for
+// a minimum age at issue. `op` is one of `<`, `<=`, `>`, `>=`, `=` and `!=`
+// and the words are separated by spaces. `reference` stands for the
+// reference year, and checks using it are skipped when there is none.
+// `@validate` adds a check to a key on top of its rule, see
+// `src/validator.rs`, and `@redact` says how to hide a key's values when
+// output is redacted, see `src/redact.rs`.
+//
+// A height rule may have a `range=` per system of units, such as
+// `range=150cm..193cm range=59in..76in`. Metric heights are then held to the
+// metric range and imperial ones to the imperial range, so 149.9cm fails
+// even though it is taller than 59in. A height in a system without a range
+// of its own is held to all of them.
+pub struct RuleSet {
+    documents: Vec<DocumentRules>,
+}
+
+#[derive(Clone)]
+pub struct DocumentRules {
+    pub name: String,
+    pub fields: Vec<FieldRule>,
+    pub unknown_keys: UnknownKeys,
+    pub duplicate_keys: DuplicateKeys,
+    pub policies: Vec<(String, Query)>,
+    pub checks: Vec<RecordCheck>,
+    pub reference_year: Option<i64>,
+    // Validators added to a key on top of its rule.
+    validators: Vec<(String, Arc<dyn FieldValidator>)>,
+    pub redactions: Vec<(String, Redaction)>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnknownKeys {
+    Allow,
+    Warn,
+    Reject,
+}
+
+// Which value counts when a key is repeated within one record.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DuplicateKeys {
+    FirstWins,
+    LastWins,
+    Reject,
+}
+
+impl DuplicateKeys {
+    pub fn parse(s: &str) -> Option<DuplicateKeys> {
+        match s {
+            "first" => Some(DuplicateKeys::FirstWins),
+            "last" => Some(DuplicateKeys::LastWins),
+            "reject" => Some(DuplicateKeys::Reject),
+            _ => None,
+        }
+    }
+}
+
+impl UnknownKeys {
+    pub fn parse(s: &str) -> Option<UnknownKeys> {
+        match s {
+            "allow" => Some(UnknownKeys::Allow),
+            "warn" => Some(UnknownKeys::Warn),
+            "reject" => Some(UnknownKeys::Reject),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FieldRule {
+    pub key: String,
+    pub kind: Kind,
+    pub required: bool,
+    constraints: Vec<Constraint>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Kind {
+    Number,
+    Height,
+    HexColor,
+    Text,
+}
+
+// A value and the reason it should fail for, `None` if it should pass.
+pub type Sample = (String, Option<Reason>);
+
+#[derive(Clone)]
+enum Constraint {
+    Range(u32, u32),
+    LengthRange(Dimension, Dimension),
+    Units(Vec<DimensionType>),
+    Case(ColorCase),
+    ShortColors(bool),
+    Len(usize),
+    Pattern(Pattern),
+    Values(Vec<String>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RulesError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl RuleSet {
+    pub fn parse(s: &str) -> Result<RuleSet, RulesError> {
+        let mut documents: Vec<DocumentRules> = vec![];
+        for (index, line) in s.lines().enumerate() {
+            let error = |message: String| RulesError {
+                line: index + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                documents.push(DocumentRules {
+                    name: line[1..line.len() - 1].trim().to_string(),
+                    fields: vec![],
+                    unknown_keys: UnknownKeys::Warn,
+                    duplicate_keys: DuplicateKeys::Reject,
+                    policies: vec![],
+                    checks: vec![],
+                    reference_year: None,
+                    validators: vec![],
+                    redactions: vec![],
+                });
+                continue;
+            }
+
+            let document = documents
+                .last_mut()
+                .ok_or_else(|| error("rule outside of a [document] section".to_string()))?;
+            // Checks hold their own operators, `=` included, so they don't
+            // split into a name and a value like the other settings.
+            if let Some(check) = line.strip_prefix("@check ") {
+                document
+                    .checks
+                    .push(RecordCheck::parse(check).map_err(error)?);
+                continue;
+            }
+            if let Some(setting) = line.strip_prefix('@') {
+                let (name, value) = setting
+                    .split_once('=')
+                    .map(|(n, v)| (n.trim(), v.trim()))
+                    .ok_or_else(|| {
+                        error(format!("expected `@<setting> = <value>`, got {}", line))
+                    })?;
+                match name {
+                    "unknown-keys" => {
+                        document.unknown_keys = UnknownKeys::parse(value).ok_or_else(|| {
+                            error(format!("invalid unknown-keys policy {}", value))
+                        })?
+                    }
+                    "duplicate-keys" => {
+                        document.duplicate_keys = DuplicateKeys::parse(value).ok_or_else(|| {
+                            error(format!("invalid duplicate-keys policy {}", value))
+                        })?
+                    }
+                    "reference-year" => {
+                        document.reference_year = Some(
+                            value
+                                .parse()
+                                .map_err(|_| error(format!("invalid year {}", value)))?,
+                        )
+                    }
+                    _ if name.starts_with("validate ") => {
+                        let key = name["validate ".len()..].trim();
+                        let (validator, arg) = match value.split_once(char::is_whitespace) {
+                            Some((validator, arg)) => (validator, Some(arg.trim())),
+                            None => (value, None),
+                        };
+                        let validator = validator::named(validator, arg).map_err(error)?;
+                        document.register(key, validator);
+                    }
+                    _ if name.starts_with("redact ") => {
+                        let key = name["redact ".len()..].trim();
+                        let redaction = Redaction::parse(value)
+                            .map_err(|e| error(format!("invalid redaction for {}, {}", key, e)))?;
+                        document.redactions.push((key.to_string(), redaction));
+                    }
+                    _ if name.starts_with("policy ") => {
+                        let name = name["policy ".len()..].trim();
+                        if policy::BUILT_IN.contains(&name)
+                            || document.policies.iter().any(|(n, _)| n == name)
+                        {
+                            return Err(error(format!("policy {} is already defined", name)));
+                        }
+                        let query = Query::parse(value)
+                            .map_err(|e| error(format!("invalid policy {}, {}", name, e)))?;
+                        document.policies.push((name.to_string(), query));
+                    }
+                    _ => return Err(error(format!("unknown setting {}", name))),
+                }
+                continue;
+            }
+            let rule = FieldRule::parse(line).map_err(error)?;
+            if document.rule(&rule.key).is_some() {
+                return Err(error(format!("duplicate rule for {}", rule.key)));
+            }
+            document.fields.push(rule);
+        }
+
+        Ok(RuleSet { documents })
+    }
+
+    pub fn document(&self, name: &str) -> Option<&DocumentRules> {
+        self.documents.iter().find(|d| d.name == name)
+    }
+}
+
+impl DocumentRules {
+    pub fn rule(&self, key: &str) -> Option<&FieldRule> {
+        self.fields.iter().find(|r| r.key == key)
+    }
+
+    pub fn register(&mut self, key: &str, validator: Arc<dyn FieldValidator>) {
+        self.validators.push((key.to_string(), validator));
+    }
+
+    // The rule for `key` followed by the validators registered for it.
+    pub fn validators(&self, key: &str) -> Vec<&dyn FieldValidator> {
+        let rule = self.rule(key).map(|r| r as &dyn FieldValidator);
+        let registered = self
+            .validators
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_ref());
+        rule.into_iter().chain(registered).collect()
+    }
+
+    pub fn required_keys(&self) -> impl Iterator<Item = &str> {
+        self.fields
+            .iter()
+            .filter(|r| r.required)
+            .map(|r| r.key.as_str())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordCheck {
+    source: String,
+    pub key: String,
+    op: CheckOp,
+    rhs: Operand,
+    offset: i64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum CheckOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Key(String),
+    Number(i64),
+    Reference,
+}
+
+impl RecordCheck {
+    fn parse(s: &str) -> Result<RecordCheck, String> {
+        let words = s.split_whitespace().collect::<Vec<&str>>();
+        let invalid = || format!("expected `<key> <op> <operand> [+ N]`, got {}", s);
+        let (key, op, rhs, offset) = match words[..] {
+            [key, op, rhs] => (key, op, rhs, 0),
+            [key, op, rhs, sign @ ("+" | "-"), n] => {
+                let n = n.parse::<i64>().map_err(|_| invalid())?;
+                (key, op, rhs, if sign == "-" { -n } else { n })
+            }
+            _ => return Err(invalid()),
+        };
+        let op = match op {
+            "<" => CheckOp::Lt,
+            "<=" => CheckOp::Le,
+            ">" => CheckOp::Gt,
+            ">=" => CheckOp::Ge,
+            "=" => CheckOp::Eq,
+            "!=" => CheckOp::Ne,
+            _ => return Err(format!("unknown operator {}", op)),
+        };
+        let rhs = match rhs.parse::<i64>() {
+            Ok(n) => Operand::Number(n),
+            Err(_) if rhs == "reference" => Operand::Reference,
+            Err(_) => Operand::Key(rhs.to_string()),
+        };
+
+        Ok(RecordCheck {
+            source: words.join(" "),
+            key: key.to_string(),
+            op,
+            rhs,
+            offset,
+        })
+    }
+
+    // `None` when `value` has nothing for a key, as when it is missing or
+    // fails its own rule, which the field checks report on their own, or when
+    // there is no reference year to compare to.
+    pub fn holds(
+        &self,
+        value: impl Fn(&str) -> Option<i64>,
+        reference: Option<i64>,
+    ) -> Option<bool> {
+        let lhs = value(&self.key)?;
+        let rhs = match &self.rhs {
+            Operand::Key(key) => value(key)?,
+            Operand::Number(n) => *n,
+            Operand::Reference => reference?,
+        } + self.offset;
+        Some(match self.op {
+            CheckOp::Lt => lhs < rhs,
+            CheckOp::Le => lhs <= rhs,
+            CheckOp::Gt => lhs > rhs,
+            CheckOp::Ge => lhs >= rhs,
+            CheckOp::Eq => lhs == rhs,
+            CheckOp::Ne => lhs != rhs,
+        })
+    }
+}
+
+impl fmt::Display for RecordCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn parse_range(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid range {}", s);
+    let (lo, hi) = s.split_once("..").ok_or_else(invalid)?;
+    let lo = lo.parse::<u32>().map_err(|_| invalid())?;
+    let hi = hi.parse::<u32>().map_err(|_| invalid())?;
+    if lo > hi {
+        return Err(invalid());
+    }
+    Ok((lo, hi))
+}
+
+impl FieldRule {
+    fn parse(line: &str) -> Result<FieldRule, String> {
+        let (key, spec) = line
+            .split_once('=')
+            .ok_or_else(|| format!("expected `<key> = <type> ...`, got {}", line))?;
+        let mut words = spec.split_whitespace();
+        let kind = match words.next() {
+            Some("number") => Kind::Number,
+            Some("height") => Kind::Height,
+            Some("hexcolor") => Kind::HexColor,
+            Some("text") => Kind::Text,
+            Some(other) => return Err(format!("unknown type {}", other)),
+            None => return Err("missing type".to_string()),
+        };
+
+        let mut required = false;
+        let mut constraints = vec![];
+        for word in words {
+            if word == "required" {
+                required = true;
+                continue;
+            }
+            let (name, arg) = word
+                .split_once('=')
+                .ok_or_else(|| format!("unknown flag {}", word))?;
+            let constraint = match (kind, name) {
+                (_, "len") => {
+                    Constraint::Len(arg.parse().map_err(|_| format!("invalid length {}", arg))?)
+                }
+                (_, "pattern") => Constraint::Pattern(Pattern::parse(arg)?),
+                (_, "values") => Constraint::Values(arg.split('|').map(String::from).collect()),
+                (Kind::Number, "range") => {
+                    let (lo, hi) = parse_range(arg)?;
+                    Constraint::Range(lo, hi)
+                }
+                (Kind::Height, "range") => {
+                    let invalid = || format!("invalid height range {}", arg);
+                    let (lo, hi) = arg.split_once("..").ok_or_else(invalid)?;
+                    let length = |s: &str| {
+                        Dimension::parse(s)
+                            .filter(|d| d.micrometres().is_some())
+                            .ok_or_else(invalid)
+                    };
+                    let (lo, hi) = (length(lo)?, length(hi)?);
+                    if lo.1.metric() != hi.1.metric() {
+                        return Err(format!("height range {} mixes metric and imperial", arg));
+                    }
+                    Constraint::LengthRange(lo, hi)
+                }
+                (Kind::Height, "units") => Constraint::Units(
+                    arg.split('|')
+                        .map(|u| DimensionType::from_suffix(u).ok_or(format!("unknown unit {}", u)))
+                        .collect::<Result<Vec<DimensionType>, String>>()?,
+                ),
+                (Kind::HexColor, "case") => Constraint::Case(
+                    ColorCase::parse(arg).ok_or(format!("unknown letter case {}", arg))?,
+                ),
+                (Kind::HexColor, "short") => Constraint::ShortColors(match arg {
+                    "allow" => true,
+                    "reject" => false,
+                    _ => return Err(format!("expected allow or reject, got {}", arg)),
+                }),
+                _ => return Err(format!("{} does not apply to {:?} values", name, kind)),
+            };
+            constraints.push(constraint);
+        }
+
+        Ok(FieldRule {
+            key: key.trim().to_string(),
+            kind,
+            required,
+            constraints,
+        })
+    }
+
+    pub fn check(&self, value: &str) -> Result<(), Reason> {
+        match self.kind {
+            Kind::Number if value.parse::<u32>().is_err() => return Err(Reason::NotANumber),
+            Kind::Height => self.check_height(value)?,
+            Kind::HexColor => self.check_color(value)?,
+            _ => {}
+        }
+
+        for constraint in self.constraints.iter() {
+            match constraint {
+                Constraint::Range(min, max) => {
+                    if !(*min..=*max).contains(&value.parse::<u32>().unwrap()) {
+                        return Err(Reason::OutOfRange {
+                            min: *min,
+                            max: *max,
+                        });
+                    }
+                }
+                Constraint::LengthRange(..)
+                | Constraint::Units(..)
+                | Constraint::Case(..)
+                | Constraint::ShortColors(..) => {}
+                Constraint::Len(len) => {
+                    if value.chars().count() != *len {
+                        return Err(Reason::WrongLength { expected: *len });
+                    }
+                }
+                Constraint::Pattern(pattern) => {
+                    if !pattern.matches(value) {
+                        return Err(Reason::PatternMismatch {
+                            pattern: pattern.to_string(),
+                        });
+                    }
+                }
+                Constraint::Values(values) => {
+                    if !values.iter().any(|v| v == value) {
+                        return Err(Reason::NotAllowed {
+                            values: values.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn color_options(&self) -> (ColorCase, bool) {
+        let mut case = ColorCase::Any;
+        let mut short = true;
+        for constraint in self.constraints.iter() {
+            match constraint {
+                Constraint::Case(c) => case = *c,
+                Constraint::ShortColors(allowed) => short = *allowed,
+                _ => {}
+            }
+        }
+        (case, short)
+    }
+
+    fn check_color(&self, value: &str) -> Result<(), Reason> {
+        let (case, short) = self.color_options();
+        match HexColor(value.to_string()).parse(case, short) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(Reason::MalformedColor { error }),
+        }
+    }
+
+    // The `range=`s a height in `unit` is held to: those in its own system
+    // of units, or all of them when there are none in it.
+    fn length_ranges(&self, unit: DimensionType) -> Vec<(Dimension, Dimension)> {
+        let ranges = self
+            .constraints
+            .iter()
+            .filter_map(|c| match c {
+                Constraint::LengthRange(min, max) => Some((*min, *max)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let own = ranges
+            .iter()
+            .copied()
+            .filter(|(min, _)| min.1.metric() == unit.metric())
+            .collect::<Vec<_>>();
+        if own.is_empty() {
+            ranges
+        } else {
+            own
+        }
+    }
+
+    // Heights need a unit, optionally one from `units=`, and have to fall in
+    // one of their `range=`s, compared in micrometres.
+    fn check_height(&self, value: &str) -> Result<(), Reason> {
+        let dimension = match Dimension::parse(value) {
+            Some(dimension) => dimension,
+            None if value.starts_with(|c: char| c.is_ascii_digit()) => return Err(Reason::BadUnit),
+            None => return Err(Reason::NotANumber),
+        };
+        let micrometres = dimension.micrometres().ok_or(Reason::BadUnit)?;
+        let allowed = self.constraints.iter().all(|c| match c {
+            Constraint::Units(units) => units.contains(&dimension.1),
+            _ => true,
+        });
+        if !allowed {
+            return Err(Reason::BadUnit);
+        }
+        let ranges = self.length_ranges(dimension.1);
+        let inside = |(min, max): &(Dimension, Dimension)| {
+            (min.micrometres().unwrap()..=max.micrometres().unwrap()).contains(&micrometres)
+        };
+        match ranges.first() {
+            Some((min, max)) if !ranges.iter().any(inside) => Err(Reason::LengthOutOfRange {
+                min: min.normalized().unwrap().to_string(),
+                max: max.normalized().unwrap().to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Values on either side of the rule's edges, for generating test batches:
+    // the first and last number of a range and the ones just outside it, the
+    // shortest and tallest whole height in centimetres and inches, each
+    // allowed value and one that isn't, and so on. Constraints are taken one at a
+    // time, so a rule that combines, say, `len=` with `range=` can fail a
+    // sample for another reason than the one given. Keys with a `pattern=`
+    // get no samples, as there's no telling what text would match.
+    pub fn samples(&self) -> Vec<Sample> {
+        if self
+            .constraints
+            .iter()
+            .any(|c| matches!(c, Constraint::Pattern(..)))
+        {
+            return vec![];
+        }
+        let mut samples = vec![];
+        match self.kind {
+            Kind::Number => match self.find(|c| match c {
+                Constraint::Range(min, max) => Some((*min, *max)),
+                _ => None,
+            }) {
+                Some((min, max)) => {
+                    samples.push((min.to_string(), None));
+                    samples.push(((min + (max - min) / 2).to_string(), None));
+                    samples.push((max.to_string(), None));
+                    let reason = Reason::OutOfRange { min, max };
+                    if min > 0 {
+                        samples.push(((min - 1).to_string(), Some(reason.clone())));
+                    }
+                    if max < u32::MAX {
+                        samples.push(((max + 1).to_string(), Some(reason)));
+                    }
+                }
+                None => samples.push(("1".to_string(), None)),
+            },
+            Kind::Height => self.height_samples(&mut samples),
+            Kind::HexColor => {
+                let (case, short) = self.color_options();
+                let malformed = |error| Some(Reason::MalformedColor { error });
+                for color in ["#123abc", "#fffffd", "#7d3b0c"].iter() {
+                    match case {
+                        ColorCase::Upper => samples.push((color.to_uppercase(), None)),
+                        ColorCase::Lower | ColorCase::Any => {
+                            samples.push((color.to_string(), None))
+                        }
+                    }
+                }
+                match case {
+                    ColorCase::Lower => {
+                        samples.push(("#ABC123".to_string(), malformed(ColorError::WrongCase('A'))))
+                    }
+                    ColorCase::Upper => {
+                        samples.push(("#abc123".to_string(), malformed(ColorError::WrongCase('a'))))
+                    }
+                    ColorCase::Any => samples.push(("#ABC123".to_string(), None)),
+                }
+                let short_form = match case {
+                    ColorCase::Upper => "#ABC",
+                    ColorCase::Lower | ColorCase::Any => "#abc",
+                };
+                samples.push((
+                    short_form.to_string(),
+                    if short {
+                        None
+                    } else {
+                        malformed(ColorError::WrongLength(3))
+                    },
+                ));
+                samples.push(("123456".to_string(), malformed(ColorError::MissingHash)));
+                samples.push(("#12345z".to_string(), malformed(ColorError::NotHex('z'))));
+                samples.push((
+                    "#1234567".to_string(),
+                    malformed(ColorError::WrongLength(7)),
+                ));
+            }
+            Kind::Text => {
+                let digits = |n: usize| "0123456789".chars().cycle().skip(n).take(n).collect();
+                let values = self.find(|c| match c {
+                    Constraint::Values(values) => Some(values.clone()),
+                    _ => None,
+                });
+                let len = self.find(|c| match c {
+                    Constraint::Len(len) => Some(*len),
+                    _ => None,
+                });
+                match (values, len) {
+                    (Some(values), _) => {
+                        values.iter().for_each(|v| samples.push((v.clone(), None)));
+                        if !values.iter().any(|v| v == "zzz") {
+                            samples.push(("zzz".to_string(), Some(Reason::NotAllowed { values })));
+                        }
+                    }
+                    (None, Some(len)) => {
+                        samples.push((digits(len), None));
+                        let reason = Reason::WrongLength { expected: len };
+                        if len > 1 {
+                            samples.push((digits(len - 1), Some(reason.clone())));
+                        }
+                        samples.push((digits(len + 1), Some(reason)));
+                    }
+                    (None, None) => samples.push(("147".to_string(), None)),
+                }
+            }
+        }
+        samples
+    }
+
+    fn find<T>(&self, f: impl Fn(&Constraint) -> Option<T>) -> Option<T> {
+        self.constraints.iter().find_map(f)
+    }
+
+    // Heights are sampled in whole centimetres and inches, along with one
+    // value in each unit `units=` leaves out and one without a unit.
+    fn height_samples(&self, samples: &mut Vec<Sample>) {
+        let units = self.find(|c| match c {
+            Constraint::Units(units) => Some(units.clone()),
+            _ => None,
+        });
+        let allowed = |unit: DimensionType| units.as_ref().is_none_or(|u| u.contains(&unit));
+        // Somewhere inside the metric range, 170cm when there isn't one.
+        let middle = match self.length_ranges(DimensionType::Cm).first() {
+            Some((min, max)) => (min.micrometres().unwrap() + max.micrometres().unwrap()) / 2,
+            None => 1_700_000,
+        };
+        let in_unit = |micrometres: u64, unit: DimensionType| {
+            let per_unit = unit.micrometres_per_unit().unwrap();
+            Dimension(micrometres * MICROS / per_unit, unit)
+        };
+
+        for unit in [DimensionType::Cm, DimensionType::In].iter().copied() {
+            if !allowed(unit) {
+                continue;
+            }
+            let per_unit = unit.micrometres_per_unit().unwrap();
+            let (min, max) = match self.length_ranges(unit).first() {
+                Some(range) => *range,
+                None => {
+                    samples.push((in_unit(middle, unit).to_string(), None));
+                    continue;
+                }
+            };
+            let first = min.micrometres().unwrap().div_ceil(per_unit);
+            let last = max.micrometres().unwrap() / per_unit;
+            let reason = Reason::LengthOutOfRange {
+                min: min.normalized().unwrap().to_string(),
+                max: max.normalized().unwrap().to_string(),
+            };
+            let whole = |n: u64| Dimension(n * MICROS, unit).to_string();
+            samples.push((whole(first), None));
+            samples.push((whole(last), None));
+            if first > 0 {
+                samples.push((whole(first - 1), Some(reason.clone())));
+            }
+            samples.push((whole(last + 1), Some(reason)));
+        }
+        for unit in [
+            DimensionType::Mm,
+            DimensionType::Cm,
+            DimensionType::M,
+            DimensionType::In,
+        ]
+        .iter()
+        .copied()
+        {
+            if !allowed(unit) {
+                samples.push((in_unit(middle, unit).to_string(), Some(Reason::BadUnit)));
+            }
+        }
+        let unitless = in_unit(middle, DimensionType::Cm).0 / MICROS;
+        samples.push((unitless.to_string(), Some(Reason::BadUnit)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_rule_files() {
+        let rules = RuleSet::parse(
+            "# comment\n[passport]\nbyr = number required range=1920..2002\ncid = text\n\n[other]\nx = text required values=a|b",
+        )
+        .unwrap();
+        let passport = rules.document("passport").unwrap();
+        assert_eq!(passport.fields.len(), 2);
+        assert_eq!(passport.required_keys().collect::<Vec<&str>>(), vec!["byr"]);
+        let byr = passport.rule("byr").unwrap();
+        assert_eq!(byr.check("2002"), Ok(()));
+        assert_eq!(
+            byr.check("2003"),
+            Err(Reason::OutOfRange {
+                min: 1920,
+                max: 2002
+            })
+        );
+        assert_eq!(byr.check("abc"), Err(Reason::NotANumber));
+        let x = rules.document("other").unwrap().rule("x").unwrap();
+        assert_eq!(x.check("b"), Ok(()));
+        assert!(matches!(x.check("c"), Err(Reason::NotAllowed { .. })));
+        assert!(rules.document("missing").is_none());
+        assert_eq!(passport.unknown_keys, UnknownKeys::Warn);
+
+        let rules = RuleSet::parse("[p]\n@unknown-keys = reject\n@duplicate-keys = last").unwrap();
+        assert_eq!(
+            rules.document("p").unwrap().unknown_keys,
+            UnknownKeys::Reject
+        );
+        assert_eq!(
+            rules.document("p").unwrap().duplicate_keys,
+            DuplicateKeys::LastWins
+        );
+        assert!(RuleSet::parse("[p]\n@unknown-keys = maybe").is_err());
+        assert!(RuleSet::parse("[p]\n@colour = red").is_err());
+        let rules = RuleSet::parse(
+            "[p]\n@check iyr >= byr + 16\n@check eyr != reference\n@check byr < iyr",
+        )
+        .unwrap();
+        let checks = &rules.document("p").unwrap().checks;
+        let years = |key: &str| match key {
+            "byr" => Some(2000),
+            "iyr" => Some(2015),
+            _ => None,
+        };
+        assert_eq!(checks[0].holds(years, None), Some(false));
+        assert_eq!(checks[0].to_string(), "iyr >= byr + 16");
+        assert_eq!(checks[1].holds(|_| Some(2020), Some(2020)), Some(false));
+        assert_eq!(checks[1].holds(|_| Some(2020), None), None);
+        assert_eq!(checks[2].holds(years, None), Some(true));
+        assert!(RuleSet::parse("[p]\n@check iyr >= byr +").is_err());
+        assert!(RuleSet::parse("[p]\n@check iyr => byr").is_err());
+
+        let rules = RuleSet::parse(
+            "[p]\npid = text len=3\n@validate pid = luhn\n@validate pid = values 125|188",
+        )
+        .unwrap();
+        let validators = rules.document("p").unwrap().validators("pid");
+        let names = validators.iter().map(|v| v.name()).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["rule", "luhn", "values"]);
+        assert!(validators.iter().all(|v| v.check("125").is_ok()));
+        assert!(validators.iter().any(|v| v.check("188").is_err()));
+        assert!(RuleSet::parse("[p]\n@validate pid = crc").is_err());
+
+        let rules =
+            RuleSet::parse("[p]\n@redact pid = mask keep=2\n@redact byr = bucket 5").unwrap();
+        assert_eq!(
+            rules.document("p").unwrap().redactions,
+            vec![
+                ("pid".to_string(), Redaction::Mask { keep: 2 }),
+                ("byr".to_string(), Redaction::Bucket(5))
+            ]
+        );
+        assert!(RuleSet::parse("[p]\n@redact pid = shred").is_err());
+        assert!(RuleSet::parse("[p]\n@redact byr = bucket 0").is_err());
+
+        let rules = RuleSet::parse("[p]\n@policy young = byr > 2000").unwrap();
+        assert_eq!(rules.document("p").unwrap().policies[0].0, "young");
+        assert!(RuleSet::parse("[p]\n@policy strict = byr > 2000").is_err());
+        assert!(RuleSet::parse("[p]\n@policy young = byr >").is_err());
+
+        let err = RuleSet::parse("[p]\nbyr = number range=1..x")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+        assert!(RuleSet::parse("byr = number").is_err());
+        assert!(RuleSet::parse("[p]\nbyr = color").is_err());
+        assert!(RuleSet::parse("[p]\nbyr = text range=1..2").is_err());
+        assert!(RuleSet::parse("[p]\nbyr = number range=2002..1920").is_err());
+    }
+
+    #[test]
+    fn checks_heights_per_unit() {
+        let rule = FieldRule::parse("hgt = height range=59in..76in units=cm|in|m|ftin").unwrap();
+        assert_eq!(rule.check("60in"), Ok(()));
+        assert_eq!(rule.check("190cm"), Ok(()));
+        assert_eq!(
+            rule.check("190in"),
+            Err(Reason::LengthOutOfRange {
+                min: "149.86cm".to_string(),
+                max: "193.04cm".to_string()
+            })
+        );
+        assert_eq!(rule.check("1.8m"), Ok(()));
+        assert_eq!(rule.check("5'11\""), Ok(()));
+        assert_eq!(rule.check("190"), Err(Reason::BadUnit));
+        assert_eq!(rule.check("190ft"), Err(Reason::BadUnit));
+        assert_eq!(rule.check("tall"), Err(Reason::NotANumber));
+
+        let strict =
+            FieldRule::parse("hgt = height range=150cm..193cm range=59in..76in units=cm|in")
+                .unwrap();
+        let short = Err(Reason::LengthOutOfRange {
+            min: "150cm".to_string(),
+            max: "193cm".to_string(),
+        });
+        assert_eq!(strict.check("149.9cm"), short);
+        assert_eq!(strict.check("150cm"), Ok(()));
+        assert_eq!(strict.check("59in"), Ok(()));
+        assert!(strict.check("76.5in").is_err());
+        assert_eq!(strict.check("1.5m"), Err(Reason::BadUnit));
+        assert_eq!(strict.check("1500mm"), Err(Reason::BadUnit));
+        assert_eq!(strict.check("4'11\""), Err(Reason::BadUnit));
+        let any = FieldRule::parse("hgt = height range=150cm..193cm range=59in..76in").unwrap();
+        assert_eq!(any.check("1499mm"), short);
+        assert_eq!(any.check("1.5m"), Ok(()));
+        assert_eq!(any.check("4'11\""), Ok(()));
+        assert!(FieldRule::parse("hgt = height range=150cm..76in").is_err());
+    }
+
+    #[test]
+    fn checks_color_options() {
+        let lenient = FieldRule::parse("hcl = hexcolor").unwrap();
+        assert_eq!(lenient.check("#AbC"), Ok(()));
+        let strict = FieldRule::parse("hcl = hexcolor case=lower short=reject").unwrap();
+        assert_eq!(strict.check("#abcdef"), Ok(()));
+        assert_eq!(
+            strict.check("#abc"),
+            Err(Reason::MalformedColor {
+                error: ColorError::WrongLength(3)
+            })
+        );
+        assert!(FieldRule::parse("hcl = hexcolor case=title").is_err());
+    }
+
+    #[test]
+    fn samples_both_sides_of_each_edge() {
+        let byr = FieldRule::parse("byr = number range=1920..2002").unwrap();
+        let reason = Some(Reason::OutOfRange {
+            min: 1920,
+            max: 2002,
+        });
+        assert_eq!(
+            byr.samples(),
+            vec![
+                ("1920".to_string(), None),
+                ("1961".to_string(), None),
+                ("2002".to_string(), None),
+                ("1919".to_string(), reason.clone()),
+                ("2003".to_string(), reason),
+            ]
+        );
+
+        let hgt = FieldRule::parse("hgt = height range=150cm..193cm range=59in..76in units=cm|in")
+            .unwrap();
+        let samples = hgt.samples();
+        let values = samples
+            .iter()
+            .map(|(value, _)| value.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            values,
+            vec![
+                "150cm", "193cm", "149cm", "194cm", "59in", "76in", "58in", "77in", "1715mm",
+                "1.715m", "171"
+            ]
+        );
+        for (value, reason) in samples {
+            assert_eq!(hgt.check(&value).err(), reason, "{}", value);
+        }
+
+        for line in [
+            "byr = number range=1920..4294967295",
+            "hcl = hexcolor case=lower short=reject",
+            "hcl = hexcolor case=upper",
+            "ecl = text values=amb|blu",
+            "pid = text len=9",
+        ]
+        .iter()
+        {
+            let rule = FieldRule::parse(line).unwrap();
+            assert!(rule.samples().iter().any(|(_, reason)| reason.is_none()));
+            for (value, reason) in rule.samples() {
+                assert_eq!(rule.check(&value).err(), reason, "{} {}", line, value);
+            }
+        }
+        assert!(FieldRule::parse("pid = text pattern=[0-9]+")
+            .unwrap()
+            .samples()
+            .is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::json;
+use crate::redact::Redaction;
+use crate::report::{Reason, ValidationReport};
+use crate::rules::DocumentRules;
+use crate::{Field, Passport, Year};
+
+// Aggregate counts over a batch, filled in one passport at a time.
+#[derive(Debug, Default)]
+pub struct Stats {
+    passports: u32,
+    eye_colors: BTreeMap<String, u32>,
+    // Years are grouped by decade, keyed by its first year. Birth years
+    // follow the `byr` bucket width instead when output is redacted, so the
+    // statistics don't show more than the records do.
+    birth_years: BTreeMap<u32, u32>,
+    birth_year_width: u32,
+    issue_years: BTreeMap<u32, u32>,
+    expiration_years: BTreeMap<u32, u32>,
+    // Heights are converted to centimetres and grouped in 5cm steps, keyed by
+    // the first centimetre, with heights that have no usable unit under `None`.
+    heights: BTreeMap<Option<u64>, u32>,
+    missing: BTreeMap<String, u32>,
+    // Failures per key, or per check for record-level checks.
+    failures: BTreeMap<String, u32>,
+}
+
+const MICROMETRES_PER_BUCKET: u64 = 50_000;
+
+impl Stats {
+    // `report` is the passport's validation report, which the caller usually
+    // has at hand already.
+    pub fn add(&mut self, passport: &Passport, report: &ValidationReport, rules: &DocumentRules) {
+        self.passports += 1;
+        self.birth_year_width = match rules.redactions.iter().find(|(k, _)| k == "byr") {
+            Some((_, Redaction::Bucket(width))) => u32::try_from(*width).unwrap_or(u32::MAX),
+            _ => 10,
+        };
+        for key in ["byr", "iyr", "eyr", "hgt", "ecl"].iter() {
+            let field = match passport.get(key, rules) {
+                Some(field) => field,
+                None => continue,
+            };
+            let decade = field.value().parse::<u32>().ok().map(|year| year / 10 * 10);
+            match (field, decade) {
+                (Field::EyeColor(color), _) => count(&mut self.eye_colors, color.0.clone()),
+                (Field::BirthYear(Year(year)), _) => {
+                    let width = self.birth_year_width;
+                    count(&mut self.birth_years, year / width * width)
+                }
+                (Field::IssueYear(..), Some(decade)) => count(&mut self.issue_years, decade),
+                (Field::ExpirationYear(..), Some(decade)) => {
+                    count(&mut self.expiration_years, decade)
+                }
+                (Field::Height(height), _) => {
+                    let bucket = height
+                        .micrometres()
+                        .map(|um| um / MICROMETRES_PER_BUCKET * 5);
+                    count(&mut self.heights, bucket)
+                }
+                _ => {}
+            }
+        }
+
+        for failure in report.failures.iter() {
+            match &failure.reason {
+                Reason::Missing => count(&mut self.missing, failure.key.clone()),
+                Reason::Inconsistent { check } => count(&mut self.failures, check.clone()),
+                _ => count(&mut self.failures, failure.key.clone()),
+            }
+        }
+    }
+
+    fn sections(&self) -> Vec<(&'static str, Vec<(String, u32)>)> {
+        let decades = |years: &BTreeMap<u32, u32>| {
+            years
+                .iter()
+                .map(|(decade, n)| (format!("{}s", decade), *n))
+                .collect()
+        };
+        let birth_years = match self.birth_year_width {
+            10 => decades(&self.birth_years),
+            width => self
+                .birth_years
+                .iter()
+                .map(|(first, n)| (format!("{}-{}", first, first + width - 1), *n))
+                .collect(),
+        };
+        let labelled = |counts: &BTreeMap<String, u32>| {
+            counts
+                .iter()
+                .map(|(label, n)| (label.clone(), *n))
+                .collect()
+        };
+        let heights = self
+            .heights
+            .iter()
+            .map(|(bucket, n)| match bucket {
+                Some(cm) => (format!("{}-{}cm", cm, cm + 4), *n),
+                None => ("no unit".to_string(), *n),
+            })
+            .collect();
+        vec![
+            ("eye_colors", labelled(&self.eye_colors)),
+            ("birth_years", birth_years),
+            ("issue_years", decades(&self.issue_years)),
+            ("expiration_years", decades(&self.expiration_years)),
+            ("heights", heights),
+            ("missing", labelled(&self.missing)),
+            ("failures", labelled(&self.failures)),
+        ]
+    }
+
+    // Each section is an object from label to count, except `missing` and
+    // `failures`, which also give the rate over all passports.
+    pub fn to_json(&self) -> String {
+        let mut members = vec![("passports", self.passports.to_string())];
+        for (name, rows) in self.sections() {
+            let rows = rows
+                .iter()
+                .map(|(label, n)| {
+                    let value = if name == "missing" || name == "failures" {
+                        json::object(&[
+                            ("count", n.to_string()),
+                            ("rate", format!("{:.4}", self.rate(*n))),
+                        ])
+                    } else {
+                        n.to_string()
+                    };
+                    (label.as_str(), value)
+                })
+                .collect::<Vec<(&str, String)>>();
+            members.push((name, json::object(&rows)));
+        }
+        json::object(&members)
+    }
+
+    fn rate(&self, n: u32) -> f64 {
+        if self.passports == 0 {
+            0.0
+        } else {
+            n as f64 / self.passports as f64
+        }
+    }
+}
+
+fn count<K: Ord>(counts: &mut BTreeMap<K, u32>, key: K) {
+    *counts.entry(key).or_insert(0) += 1;
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "passports: {}", self.passports)?;
+        for (name, rows) in self.sections() {
+            writeln!(f, "{}:", name.replace('_', " "))?;
+            let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+            for (label, n) in rows {
+                write!(f, "  {:<width$}  {:>5}", label, n, width = width)?;
+                if name == "missing" || name == "failures" {
+                    write!(f, "  {:>5.1}%", self.rate(n) * 100.0)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rules::RuleSet;
+    use crate::DEFAULT_RULES;
+
+    #[test]
+    fn aggregates_a_batch() {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let rules = rules.document("passport").unwrap();
+        let mut stats = Stats::default();
+        for fields in [
+            "byr:1937 iyr:2017 eyr:2020 hgt:183cm hcl:#fffffd ecl:gry pid:860033327",
+            "byr:1942 iyr:2015 eyr:2010 hgt:72in ecl:gry pid:1",
+            "byr:2010 hgt:170",
+        ]
+        .iter()
+        {
+            let mut pass = Passport::new();
+            pass.load_fields(fields);
+            stats.add(&pass, &pass.validate(rules), rules);
+        }
+
+        assert_eq!(
+            stats.to_string(),
+            "passports: 3\n\
+             eye colors:\n  gry      2\n\
+             birth years:\n  1930s      1\n  1940s      1\n  2010s      1\n\
+             issue years:\n  2010s      2\n\
+             expiration years:\n  2010s      1\n  2020s      1\n\
+             heights:\n  no unit        1\n  180-184cm      2\n\
+             missing:\n  ecl      1   33.3%\n  eyr      1   33.3%\n  hcl      2   66.7%\n  \
+             iyr      1   33.3%\n  pid      1   33.3%\n\
+             failures:\n  byr      1   33.3%\n  eyr      1   33.3%\n  \
+             hgt      1   33.3%\n  pid      1   33.3%\n"
+        );
+        assert!(stats.to_json().starts_with(
+            "{\"passports\":3,\"eye_colors\":{\"gry\":2},\"birth_years\":{\"1930s\":1,"
+        ));
+        assert!(stats
+            .to_json()
+            .contains("\"missing\":{\"ecl\":{\"count\":1,\"rate\":0.3333},"));
+
+        let mut redacted = rules.clone();
+        redacted.redactions = vec![("byr".to_string(), Redaction::Bucket(25))];
+        let mut stats = Stats::default();
+        for fields in ["byr:1937", "byr:1942", "byr:1951"].iter() {
+            let mut pass = Passport::new();
+            pass.load_fields(fields);
+            stats.add(&pass, &pass.validate(&redacted), &redacted);
+        }
+        assert!(stats
+            .to_string()
+            .contains("birth years:\n  1925-1949      2\n  1950-1974      1\n"));
+    }
+}
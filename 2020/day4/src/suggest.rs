@@ -0,0 +1,229 @@
+use std::fmt;
+
+use crate::report::{Reason, ValidationReport};
+use crate::rules::DocumentRules;
+use crate::{ColorCase, ColorError, Field, HexColor, Passport};
+
+// How sure a suggestion is. `High` fixes only change how a value is written
+// (letter case, a leading zero, the short color form), `Medium` ones guess at
+// something missing but only one guess passes the rules, and `Low` means
+// several corrections pass and a person has to pick.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn parse(s: &str) -> Option<Confidence> {
+        match s {
+            "low" => Some(Confidence::Low),
+            "medium" => Some(Confidence::Medium),
+            "high" => Some(Confidence::High),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Confidence::Low => write!(f, "low"),
+            Confidence::Medium => write!(f, "medium"),
+            Confidence::High => write!(f, "high"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Suggestion {
+    // Index of the failing field in the passport.
+    pub field: usize,
+    pub key: String,
+    pub value: String,
+    // Values that pass every check on the key, most likely first.
+    pub candidates: Vec<String>,
+    pub confidence: Confidence,
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let candidates = self
+            .candidates
+            .iter()
+            .map(|c| format!("{}:{}", self.key, c))
+            .collect::<Vec<String>>();
+        write!(
+            f,
+            "{}:{} -> {} ({})",
+            self.key,
+            self.value,
+            candidates.join(" or "),
+            self.confidence
+        )
+    }
+}
+
+// Suggestions for the failing fields in `report`. Fields with no fix that
+// passes the rules get none.
+pub fn suggest(report: &ValidationReport, rules: &DocumentRules) -> Vec<Suggestion> {
+    let mut suggestions = vec![];
+    for failure in report.failures.iter() {
+        let (index, value) = match (failure.field, &failure.value) {
+            (Some(index), Some(value)) => (index, value),
+            _ => continue,
+        };
+        let key = &failure.key;
+        let mut guesses = guesses(value, &failure.reason);
+        guesses.retain(|(candidate, _)| {
+            let field = Field::from(format!("{}:{}", key, candidate).as_str());
+            field.check(rules).is_ok()
+        });
+        let confidence = match guesses.len() {
+            0 => continue,
+            1 => guesses[0].1,
+            _ => Confidence::Low,
+        };
+        suggestions.push(Suggestion {
+            field: index,
+            key: key.clone(),
+            value: value.clone(),
+            candidates: guesses.into_iter().map(|(c, _)| c).collect(),
+            confidence,
+        });
+    }
+    suggestions
+}
+
+// Possible corrections for `value`, before checking them against the rules.
+fn guesses(value: &str, reason: &Reason) -> Vec<(String, Confidence)> {
+    match reason {
+        Reason::BadUnit if value.chars().all(|c| c.is_ascii_digit() || c == '.') => ["cm", "in"]
+            .iter()
+            .map(|unit| (format!("{}{}", value, unit), Confidence::Medium))
+            .collect(),
+        Reason::MalformedColor { error } => match error {
+            ColorError::WrongCase(_) => vec![
+                (value.to_lowercase(), Confidence::High),
+                (value.to_uppercase(), Confidence::High),
+            ],
+            ColorError::WrongLength(3) => HexColor(value.to_string())
+                .parse(ColorCase::Any, true)
+                .map(|rgb| vec![(rgb.to_string(), Confidence::High)])
+                .unwrap_or_default(),
+            ColorError::MissingHash => vec![(format!("#{}", value), Confidence::Medium)],
+            _ => vec![],
+        },
+        Reason::WrongLength { expected }
+            if value.chars().all(|c| c.is_ascii_digit()) && value.len() < *expected =>
+        {
+            let confidence = if expected - value.len() == 1 {
+                Confidence::High
+            } else {
+                Confidence::Medium
+            };
+            vec![(format!("{:0>width$}", value, width = expected), confidence)]
+        }
+        Reason::NotAllowed { values } => {
+            let lower = value.to_lowercase();
+            let mut guesses = values
+                .iter()
+                .filter_map(|allowed| {
+                    if *allowed == lower {
+                        Some((allowed.clone(), Confidence::High))
+                    } else if lower.starts_with(allowed.as_str())
+                        || allowed.starts_with(lower.as_str())
+                        || distance(&lower, allowed) == 1
+                    {
+                        Some((allowed.clone(), Confidence::Medium))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<(String, Confidence)>>();
+            // An exact match ignoring case beats any near miss.
+            if guesses.iter().any(|(_, c)| *c == Confidence::High) {
+                guesses.retain(|(_, c)| *c == Confidence::High);
+            }
+            guesses
+        }
+        _ => vec![],
+    }
+}
+
+// Levenshtein distance, for the short codes in `values=` lists.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut row = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// Replaces each field that has a single suggestion at `min` confidence or
+// better, returning how many were replaced.
+pub fn apply(passport: &mut Passport, suggestions: &[Suggestion], min: Confidence) -> usize {
+    let mut applied = 0;
+    for suggestion in suggestions {
+        if suggestion.confidence >= min && suggestion.candidates.len() == 1 {
+            let fixed = format!("{}:{}", suggestion.key, suggestion.candidates[0]);
+            passport.fields[suggestion.field] = fixed.as_str().into();
+            applied += 1;
+        }
+    }
+    applied
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rules::RuleSet;
+    use crate::DEFAULT_RULES;
+
+    #[test]
+    fn suggests_fixes_with_confidence() {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let rules = rules.document("passport").unwrap();
+        let mut pass = Passport::new();
+        pass.load_fields(
+            "byr:1980 iyr:2015 eyr:2025 hgt:180 hcl:#ABC123 ecl:blue pid:87499704 cid:1",
+        );
+        let suggestions = suggest(&pass.validate(rules), rules);
+        let lines = suggestions
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            lines,
+            vec![
+                "hgt:180 -> hgt:180cm (medium)",
+                "hcl:#ABC123 -> hcl:#abc123 (high)",
+                "ecl:blue -> ecl:blu (medium)",
+                "pid:87499704 -> pid:087499704 (high)",
+            ]
+        );
+
+        assert_eq!(apply(&mut pass, &suggestions, Confidence::High), 2);
+        assert!(!pass.is_valid(rules));
+        assert_eq!(apply(&mut pass, &suggestions, Confidence::Medium), 4);
+        assert!(pass.is_valid(rules));
+
+        let mut pass = Passport::new();
+        pass.load_fields("hgt:65 ecl:gr hcl:#12345z");
+        let suggestions = suggest(&pass.validate(rules), rules);
+        assert_eq!(suggestions.len(), 2);
+        assert_eq!(suggestions[0].to_string(), "hgt:65 -> hgt:65in (medium)");
+        assert_eq!(suggestions[1].candidates, vec!["gry", "grn"]);
+        assert_eq!(suggestions[1].confidence, Confidence::Low);
+        assert_eq!(distance("blue", "blu"), 1);
+        assert_eq!(distance("amb", "hzl"), 3);
+    }
+}
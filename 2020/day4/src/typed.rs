@@ -0,0 +1,196 @@
+use crate::{Color, Dimension, Field, HexColor, Passport, Year, ID};
+
+// A passport with one named slot per key, for code that wants to read
+// `passport.height` instead of scanning the field list. Unknown keys are kept
+// in `extra` so converting back to a `Passport` loses nothing but repeats.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypedPassport {
+    pub birth_year: Option<Year>,
+    pub issue_year: Option<Year>,
+    pub expiration_year: Option<Year>,
+    pub height: Option<Dimension>,
+    pub hair_color: Option<HexColor>,
+    pub eye_color: Option<Color>,
+    pub passport_id: Option<ID>,
+    pub country_id: Option<ID>,
+    pub extra: Vec<(String, String)>,
+}
+
+impl TypedPassport {
+    pub fn builder() -> PassportBuilder {
+        PassportBuilder::default()
+    }
+
+    // Stores `field` in its slot unless the slot is already filled.
+    fn set(&mut self, field: Field) {
+        fn put<T>(slot: &mut Option<T>, value: T) {
+            if slot.is_none() {
+                *slot = Some(value);
+            }
+        }
+
+        match field {
+            Field::BirthYear(year) => put(&mut self.birth_year, year),
+            Field::IssueYear(year) => put(&mut self.issue_year, year),
+            Field::ExpirationYear(year) => put(&mut self.expiration_year, year),
+            Field::Height(dimension) => put(&mut self.height, dimension),
+            Field::HairColor(color) => put(&mut self.hair_color, color),
+            Field::EyeColor(color) => put(&mut self.eye_color, color),
+            Field::PassportID(id) => put(&mut self.passport_id, id),
+            Field::CountryID(id) => put(&mut self.country_id, id),
+            Field::Unknown { key, value } => self.extra.push((key, value)),
+        }
+    }
+}
+
+// The first occurrence of a repeated key wins, like `DuplicateKeys::FirstWins`.
+impl From<Vec<Field>> for TypedPassport {
+    fn from(fields: Vec<Field>) -> Self {
+        let mut typed = TypedPassport::default();
+        for field in fields {
+            typed.set(field);
+        }
+        typed
+    }
+}
+
+impl From<&Passport> for TypedPassport {
+    fn from(passport: &Passport) -> Self {
+        passport.fields.clone().into()
+    }
+}
+
+// Lists the fields in the usual `byr iyr eyr hgt hcl ecl pid cid` order,
+// followed by the extra keys.
+impl From<TypedPassport> for Passport {
+    fn from(typed: TypedPassport) -> Self {
+        let known = vec![
+            typed.birth_year.map(Field::BirthYear),
+            typed.issue_year.map(Field::IssueYear),
+            typed.expiration_year.map(Field::ExpirationYear),
+            typed.height.map(Field::Height),
+            typed.hair_color.map(Field::HairColor),
+            typed.eye_color.map(Field::EyeColor),
+            typed.passport_id.map(Field::PassportID),
+            typed.country_id.map(Field::CountryID),
+        ];
+        let extra = typed
+            .extra
+            .into_iter()
+            .map(|(key, value)| Field::Unknown { key, value });
+        Passport {
+            fields: known.into_iter().flatten().chain(extra).collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PassportBuilder {
+    passport: TypedPassport,
+}
+
+impl PassportBuilder {
+    pub fn birth_year(mut self, year: u32) -> Self {
+        self.passport.birth_year = Some(Year(year));
+        self
+    }
+
+    pub fn issue_year(mut self, year: u32) -> Self {
+        self.passport.issue_year = Some(Year(year));
+        self
+    }
+
+    pub fn expiration_year(mut self, year: u32) -> Self {
+        self.passport.expiration_year = Some(Year(year));
+        self
+    }
+
+    // Heights that don't parse are kept as an extra `hgt` key, so the report
+    // can point at them.
+    pub fn height(mut self, height: &str) -> Self {
+        match Dimension::parse(height) {
+            Some(dimension) => self.passport.height = Some(dimension),
+            None => return self.extra("hgt", height),
+        }
+        self
+    }
+
+    // Stored as given, so the rules judge the same text a batch would hold.
+    pub fn hair_color(mut self, color: &str) -> Self {
+        self.passport.hair_color = Some(HexColor(color.to_string()));
+        self
+    }
+
+    pub fn eye_color(mut self, color: &str) -> Self {
+        self.passport.eye_color = Some(Color(color.to_string()));
+        self
+    }
+
+    pub fn passport_id(mut self, id: &str) -> Self {
+        self.passport.passport_id = Some(ID(id.to_string()));
+        self
+    }
+
+    pub fn country_id(mut self, id: &str) -> Self {
+        self.passport.country_id = Some(ID(id.to_string()));
+        self
+    }
+
+    // Adds a key outside the passport schema.
+    pub fn extra(mut self, key: &str, value: &str) -> Self {
+        self.passport
+            .extra
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn build(self) -> TypedPassport {
+        self.passport
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DimensionType, MICROS};
+
+    #[test]
+    fn converts_between_forms() {
+        let mut pass = Passport::new();
+        pass.load_fields("hgt:183cm byr:1937 foo:bar byr:1980 cid:147");
+        let typed: TypedPassport = (&pass).into();
+        assert_eq!(typed.birth_year, Some(Year(1937)));
+        assert_eq!(
+            typed.height,
+            Some(Dimension(183 * MICROS, DimensionType::Cm))
+        );
+        assert_eq!(typed.passport_id, None);
+        assert_eq!(typed.extra, vec![("foo".to_string(), "bar".to_string())]);
+
+        let pass: Passport = typed.into();
+        assert_eq!(pass.to_string(), "byr:1937 hgt:183cm cid:147 foo:bar");
+    }
+
+    #[test]
+    fn builds_passports() {
+        let typed = TypedPassport::builder()
+            .birth_year(1937)
+            .issue_year(2017)
+            .expiration_year(2020)
+            .height("183cm")
+            .hair_color("#FFFFFD")
+            .eye_color("gry")
+            .passport_id("860033327")
+            .country_id("147")
+            .extra("foo", "bar")
+            .build();
+        assert_eq!(typed.country_id, Some(ID("147".to_string())));
+        let tall: Passport = TypedPassport::builder().height("tall").build().into();
+        assert_eq!(tall.to_string(), "hgt:tall");
+        let pass: Passport = typed.into();
+        assert_eq!(
+            pass.to_string(),
+            "byr:1937 iyr:2017 eyr:2020 hgt:183cm hcl:#FFFFFD ecl:gry pid:860033327 cid:147 foo:bar"
+        );
+    }
+}
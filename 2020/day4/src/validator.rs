@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::fs;
+use std::sync::Arc;
+
+use crate::report::Reason;
+use crate::rules::FieldRule;
+
+// A check on the value of one key. The rule from the rule file is always the
+// first validator for its key; more can be registered per key in code with
+// `DocumentRules::register` or in the rule file with
+//
+//   @validate <key> = <validator> [argument]
+//
+// using one of the validators below. A value has to pass all of them.
+pub trait FieldValidator: Send + Sync {
+    fn name(&self) -> &str;
+    fn check(&self, value: &str) -> Result<(), Reason>;
+}
+
+impl FieldValidator for FieldRule {
+    fn name(&self) -> &str {
+        "rule"
+    }
+
+    fn check(&self, value: &str) -> Result<(), Reason> {
+        FieldRule::check(self, value)
+    }
+}
+
+// `luhn`: the last digit is a Luhn check digit over the others.
+pub struct Luhn;
+
+impl FieldValidator for Luhn {
+    fn name(&self) -> &str {
+        "luhn"
+    }
+
+    fn check(&self, value: &str) -> Result<(), Reason> {
+        let digits = value
+            .chars()
+            .rev()
+            .map(|c| c.to_digit(10).ok_or(Reason::NotANumber))
+            .collect::<Result<Vec<u32>, Reason>>()?;
+        let sum: u32 = digits
+            .iter()
+            .enumerate()
+            .map(|(i, &d)| match (i % 2, d * 2) {
+                (0, _) => d,
+                (_, doubled) if doubled > 9 => doubled - 9,
+                (_, doubled) => doubled,
+            })
+            .sum();
+        if digits.is_empty() || !sum.is_multiple_of(10) {
+            return Err(Reason::Invalid {
+                validator: self.name().to_string(),
+                detail: "bad check digit".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+// `table PATH`: the value is one of the codes in a local file, one per line,
+// with `#` comments. `values a|b|c` is the same with the codes inline, where
+// `#` is just another character, as in `values #fffffd|#123abc`.
+pub struct CodeTable {
+    name: &'static str,
+    source: String,
+    codes: HashSet<String>,
+}
+
+impl CodeTable {
+    fn file(path: &str, contents: &str) -> CodeTable {
+        CodeTable {
+            name: "table",
+            source: path.to_string(),
+            codes: codes(contents.lines().map(|line| line.split('#').next().unwrap())),
+        }
+    }
+
+    fn values(values: &str) -> CodeTable {
+        CodeTable {
+            name: "values",
+            source: "the allowed values".to_string(),
+            codes: codes(values.split('|')),
+        }
+    }
+}
+
+fn codes<'a>(codes: impl Iterator<Item = &'a str>) -> HashSet<String> {
+    codes
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl FieldValidator for CodeTable {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn check(&self, value: &str) -> Result<(), Reason> {
+        if !self.codes.contains(value) {
+            return Err(Reason::Invalid {
+                validator: self.name().to_string(),
+                detail: format!("not listed in {}", self.source),
+            });
+        }
+        Ok(())
+    }
+}
+
+// Builds a validator from its name and argument in a rule file.
+pub fn named(name: &str, arg: Option<&str>) -> Result<Arc<dyn FieldValidator>, String> {
+    match (name, arg) {
+        ("luhn", None) => Ok(Arc::new(Luhn)),
+        ("table", Some(path)) => {
+            let contents = fs::read_to_string(path)
+                .map_err(|e| format!("could not read code table {}, {}", path, e))?;
+            Ok(Arc::new(CodeTable::file(path, &contents)))
+        }
+        ("values", Some(values)) => Ok(Arc::new(CodeTable::values(values))),
+        ("luhn", Some(_)) => Err("luhn takes no argument".to_string()),
+        ("table", None) | ("values", None) => Err(format!("{} needs an argument", name)),
+        _ => Err(format!("unknown validator {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn runs_built_in_validators() {
+        assert_eq!(Luhn.check("79927398713"), Ok(()));
+        assert!(matches!(
+            Luhn.check("79927398710"),
+            Err(Reason::Invalid { .. })
+        ));
+        assert_eq!(Luhn.check("7a"), Err(Reason::NotANumber));
+
+        let table = CodeTable::file("codes.txt", "# ISO codes\nAT\nBE # Belgium\n\n");
+        assert_eq!(table.check("BE"), Ok(()));
+        assert_eq!(
+            table.check("XX"),
+            Err(Reason::Invalid {
+                validator: "table".to_string(),
+                detail: "not listed in codes.txt".to_string()
+            })
+        );
+
+        let colors = named("values", Some("amb|blu")).unwrap();
+        assert_eq!(colors.check("blu"), Ok(()));
+        assert_eq!(
+            colors.check("red"),
+            Err(Reason::Invalid {
+                validator: "values".to_string(),
+                detail: "not listed in the allowed values".to_string()
+            })
+        );
+        let hex = named("values", Some("#fffffd|#123abc")).unwrap();
+        assert_eq!(hex.check("#123abc"), Ok(()));
+        assert!(named("luhn", Some("x")).is_err());
+        assert!(named("table", Some("/does/not/exist")).is_err());
+        assert!(named("crc", None).is_err());
+    }
+}